@@ -0,0 +1,268 @@
+use std::path::Path;
+use toml::value::Value;
+use crate::util::*;
+use crate::error::*;
+use crate::xml::*;
+
+// A <uses-feature> entry, e.g. { name = "android.hardware.vulkan.level", version = 1 }
+// or { gl_es_version = "0x00030000" }.
+#[derive(Clone,Debug,Default)]
+pub struct UsesFeature {
+    pub name: Option<String>,
+    pub required: Option<bool>,
+    pub version: Option<i64>,
+    pub gl_es_version: Option<String>
+}
+
+impl UsesFeature {
+    fn to_element(&self)->XmlElement {
+        let mut element=XmlElement::new("uses-feature");
+        if let Some(name)=&self.name {
+            element.set_attribute("android:name",name);
+        }
+        if let Some(version)=self.version {
+            element.set_attribute("android:version",&version.to_string());
+        }
+        if let Some(gl_es_version)=&self.gl_es_version {
+            element.set_attribute("android:glEsVersion",gl_es_version);
+        }
+        if let Some(required)=self.required {
+            element.set_attribute("android:required",&required.to_string());
+        }
+        element
+    }
+}
+
+// The [package.metadata.android.manifest] section. Anything not set keeps
+// the value from SDL's template manifest.
+#[derive(Clone,Debug,Default)]
+pub struct ManifestMetadata {
+    pub permissions: Option<Vec<String>>,
+    pub uses_features: Option<Vec<UsesFeature>>,
+    pub orientation: Option<String>,
+    pub theme: Option<String>,
+    pub config_changes: Option<Vec<String>>,
+    pub is_game: Option<bool>
+}
+
+fn parse_uses_feature(manifest_path: &Path, value: &Value)->SdlApkResult<UsesFeature> {
+    let error=||SdlApkError::BadMetadata(format!(
+        "{}: package.metadata.android.manifest.uses_feature entries should be tables with name, required, version or gl_es_version",
+        manifest_path.display()
+    ));
+
+    let table=value.as_table().ok_or_else(error)?;
+    let mut feature=UsesFeature::default();
+    for (k,v) in table {
+        match (&**k,v) {
+            ("name",Value::String(s))=>feature.name=Some(s.clone()),
+            ("required",Value::Boolean(b))=>feature.required=Some(*b),
+            ("version",Value::Integer(i))=>feature.version=Some(*i),
+            ("gl_es_version",Value::String(s))=>feature.gl_es_version=Some(s.clone()),
+            _=>return Err(error())
+        }
+    }
+
+    Ok(feature)
+}
+
+pub fn get_manifest_metadata(manifest_path: &Path)->SdlApkResult<ManifestMetadata> {
+    let path=|key|vec!["package","metadata","android","manifest",key];
+
+    let uses_features=match get_toml_value(manifest_path,path("uses_feature"))? {
+        None=>None,
+        Some(Value::Array(a))=>Some(a.iter()
+            .map(|v|parse_uses_feature(manifest_path,v))
+            .collect::<SdlApkResult<Vec<UsesFeature>>>()?),
+        Some(v)=>Some(vec![parse_uses_feature(manifest_path,&v)?])
+    };
+
+    Ok(ManifestMetadata {
+        permissions: get_toml_string_array(manifest_path,path("permissions"))?,
+        uses_features,
+        orientation: get_toml_string(manifest_path,path("orientation"))?,
+        theme: get_toml_string(manifest_path,path("theme"))?,
+        config_changes: get_toml_string_array(manifest_path,path("config_changes"))?,
+        is_game: get_toml_bool(manifest_path,path("is_game"))?
+    })
+}
+
+// Render SDL's template AndroidManifest.xml for our app.
+pub fn render_android_manifest(template: &str, app_id: &str, metadata: &ManifestMetadata)->SdlApkResult<String> {
+    let mut root=XmlElement::parse(template)?;
+    if root.get_attribute("package").is_some() {
+        root.set_attribute("package",app_id);
+    }
+
+    if let Some(permissions)=&metadata.permissions {
+        root.replace_children("uses-permission",permissions.iter().map(|p|{
+            XmlElement::new("uses-permission").with_attribute("android:name",p)
+        }).collect(),"application");
+    }
+
+    if let Some(uses_features)=&metadata.uses_features {
+        root.replace_children("uses-feature",uses_features.iter().map(|f|{
+            f.to_element()
+        }).collect(),"application");
+    }
+
+    let application=root.get_child_mut("application")
+        .ok_or_else(||SdlApkError::Template("AndroidManifest.xml has no application element".to_string()))?;
+
+    if let Some(theme)=&metadata.theme {
+        application.set_attribute("android:theme",theme);
+    }
+
+    if let Some(is_game)=metadata.is_game {
+        application.set_attribute("android:isGame",&is_game.to_string());
+    }
+
+    let activity=application.get_child_mut("activity")
+        .ok_or_else(||SdlApkError::Template("AndroidManifest.xml has no activity element".to_string()))?;
+
+    activity.set_attribute("android:name","MainActivity");
+
+    if let Some(orientation)=&metadata.orientation {
+        activity.set_attribute("android:screenOrientation",orientation);
+    }
+
+    if let Some(config_changes)=&metadata.config_changes {
+        activity.set_attribute("android:configChanges",&config_changes.join("|"));
+    }
+
+    root.to_xml_string()
+}
+
+// Android string resources treat quotes and backslashes specially, and a
+// leading @ or ? as a reference.
+fn escape_string_resource(value: &str)->String {
+    let mut out=String::new();
+    if value.starts_with(['@','?']) {
+        out.push('\\');
+    }
+    for c in value.chars() {
+        if matches!(c,'\\'|'\''|'"') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+
+    out
+}
+
+// Set the app_name string of res/values/strings.xml to the title. Other
+// strings are left alone, and so is a file without app_name.
+pub fn render_strings_xml(template: &str, title: &str)->SdlApkResult<String> {
+    let mut root=XmlElement::parse(template)?;
+    for child in root.children.iter_mut() {
+        if let XmlNode::Element(e)=child {
+            if e.name=="string" && e.get_attribute("name")==Some("app_name") {
+                e.children=vec![XmlNode::Text(escape_string_resource(title))];
+            }
+        }
+    }
+
+    root.to_xml_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str=include_str!("../testdata/sdl-2.26/AndroidManifest.xml");
+    const STRINGS: &str=include_str!("../testdata/sdl-2.26/strings.xml");
+
+    fn child<'a>(element: &'a XmlElement, name: &str)->&'a XmlElement {
+        element.children.iter().find_map(|c|match c {
+            XmlNode::Element(e) if e.name==name=>Some(e),
+            _=>None
+        }).unwrap()
+    }
+
+    #[test]
+    fn default_metadata() {
+        let xml=render_android_manifest(MANIFEST,"com.example.game",&ManifestMetadata::default()).unwrap();
+        let root=XmlElement::parse(&xml).unwrap();
+        assert_eq!(root.get_attribute("package"),Some("com.example.game"));
+
+        let application=child(&root,"application");
+        assert_eq!(application.get_attribute("android:theme"),Some("@android:style/Theme.NoTitleBar.Fullscreen"));
+
+        let activity=child(application,"activity");
+        assert_eq!(activity.get_attribute("android:name"),Some("MainActivity"));
+        assert_eq!(activity.get_attribute("android:exported"),Some("true"));
+        assert!(xml.contains("android.permission.VIBRATE"));
+        assert!(xml.contains("android.hardware.usb.action.USB_DEVICE_ATTACHED"));
+    }
+
+    #[test]
+    fn metadata_is_applied() {
+        let metadata=ManifestMetadata {
+            permissions: Some(vec!["android.permission.INTERNET".to_string()]),
+            uses_features: Some(vec![UsesFeature {
+                name: Some("android.hardware.vulkan.level".to_string()),
+                required: Some(true),
+                version: Some(1),
+                gl_es_version: None
+            }]),
+            orientation: Some("landscape".to_string()),
+            theme: Some("@style/Game".to_string()),
+            config_changes: Some(vec!["orientation".to_string(),"screenSize".to_string()]),
+            is_game: Some(true)
+        };
+
+        let xml=render_android_manifest(MANIFEST,"com.example.game",&metadata).unwrap();
+        let root=XmlElement::parse(&xml).unwrap();
+        assert!(!xml.contains("VIBRATE"));
+        assert!(!xml.contains("glEsVersion"));
+
+        let names:Vec<&str>=root.children.iter().filter_map(|c|match c {
+            XmlNode::Element(e)=>Some(&*e.name),
+            _=>None
+        }).collect();
+        assert_eq!(names,vec!["uses-feature","uses-permission","application"]);
+
+        let feature=child(&root,"uses-feature");
+        assert_eq!(feature.get_attribute("android:name"),Some("android.hardware.vulkan.level"));
+        assert_eq!(feature.get_attribute("android:version"),Some("1"));
+        assert_eq!(feature.get_attribute("android:required"),Some("true"));
+
+        let application=child(&root,"application");
+        assert_eq!(application.get_attribute("android:theme"),Some("@style/Game"));
+        assert_eq!(application.get_attribute("android:isGame"),Some("true"));
+
+        let activity=child(application,"activity");
+        assert_eq!(activity.get_attribute("android:screenOrientation"),Some("landscape"));
+        assert_eq!(activity.get_attribute("android:configChanges"),Some("orientation|screenSize"));
+    }
+
+    #[test]
+    fn needs_activity() {
+        let template="<manifest><application/></manifest>";
+        assert!(matches!(
+            render_android_manifest(template,"a.b",&ManifestMetadata::default()),
+            Err(SdlApkError::Template(_))
+        ));
+    }
+
+    #[test]
+    fn strings_app_name() {
+        let xml=render_strings_xml(STRINGS,"Tom's \"Game\"").unwrap();
+        assert!(xml.contains(r#"<string name="app_name">Tom\'s \"Game\"</string>"#),"{}",xml);
+        assert!(render_strings_xml(STRINGS,"@home").unwrap().contains(">\\@home<"));
+    }
+
+    #[test]
+    fn strings_other_entries_kept() {
+        let template=r#"<resources>
+            <string name="app_name">{{title}} Deluxe</string>
+            <string name="greeting">Game on</string>
+        </resources>"#;
+        let xml=render_strings_xml(template,"Pong").unwrap();
+        assert!(xml.contains(r#"<string name="app_name">Pong</string>"#),"{}",xml);
+        assert!(xml.contains(r#"<string name="greeting">Game on</string>"#),"{}",xml);
+
+        let xml=render_strings_xml("<resources><string name=\"x\">Game</string></resources>","Pong").unwrap();
+        assert!(xml.contains(">Game<"));
+    }
+}
@@ -1,196 +1,457 @@
-use fs_extra::{copy_items, dir::CopyOptions, remove_items};
-use std::path::Path;
+use fs_extra::remove_items;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::fs::{copy, create_dir_all, read_to_string, write};
-use symlink::symlink_dir;
-use std::collections::HashMap;
+use std::fs::{copy, create_dir_all, read_dir, read_link};
+use symlink::{remove_symlink_dir, symlink_dir};
+use std::collections::{BTreeSet, HashMap};
 use crate::util::*;
-use crate::BuildProfile;
-
-pub fn build_sdl_for_android(targets: &Vec<&str>, profile:BuildProfile) {
-    let p = Path::new(&*get_env_var("ANDROID_NDK_HOME")).join("ndk-build");
-
-    assert!(Command::new(p)
-        .args([
-            "NDK_PROJECT_PATH=.",
-            "APP_BUILD_SCRIPT=./Android.mk",
-            "APP_PLATFORM=android-18"
-        ])
-        .current_dir(&*get_env_var("SDL"))
-        .status()
-        .unwrap()
-        .success());
-
-    for rust_name in targets {
-        let android_name=get_target_android_name(rust_name);
-        let rust_dir=Path::new("target")
-            .join(rust_name)
-            .join(profile.to_string())
-            .join("deps");
-
-        create_dir_all(rust_dir).expect("Unable to create target dir");
-        copy(
-            Path::new(&*get_env_var("SDL"))
-                .join("libs")
-                .join(android_name)
-                .join("libSDL2.so"),
-            Path::new("target")
-                .join(rust_name)
-                .join(profile.to_string())
-                .join("deps/libSDL2.so")
-        ).expect("Unable to copy SDL dependencies");
+use crate::error::*;
+use crate::workspace::*;
+use crate::android_manifest::*;
+use crate::icon::*;
+use crate::assets::*;
+use crate::java_sources::*;
+use crate::gradle::*;
+use crate::template::*;
+use crate::sdl::*;
+use crate::project_files::*;
+use crate::{BuildProfile, Signing};
+
+pub fn get_target_android_name(rust_target_name: &str)->SdlApkResult<&'static str> {
+    match rust_target_name {
+        "aarch64-linux-android"=>Ok("arm64-v8a"),
+        "armv7-linux-androideabi"=>Ok("armeabi-v7a"),
+        "i686-linux-android"=>Ok("x86"),
+        "x86_64-linux-android"=>Ok("x86_64"),
+        _=>Err(SdlApkError::UnsupportedTarget(rust_target_name.to_string()))
     }
 }
 
-pub fn get_target_android_name(rust_target_name: &str)->&str {
-    match rust_target_name {
-        "aarch64-linux-android"=>"arm64-v8a",
-        "armv7-linux-androideabi"=>"armeabi-v7a",
-        "i686-linux-android"=>"x86",
-        _=>{panic!("Unknown target: {}",rust_target_name)}
-    }
+pub fn get_android_build_targets(manifest_path: &Path)->SdlApkResult<Option<Vec<String>>> {
+    get_toml_string_array(manifest_path,
+        vec!["package","metadata","android","build_targets"]
+    )
+}
+
+pub fn get_android_features(manifest_path: &Path)->SdlApkResult<Vec<String>> {
+    Ok(get_toml_string_array(manifest_path,
+        vec!["package","metadata","android","features"]
+    )?.unwrap_or_default())
 }
 
-pub fn get_android_app_id(manifest_path: &Path)->String {
+pub fn get_android_default_bin(manifest_path: &Path)->SdlApkResult<Option<String>> {
     get_toml_string(manifest_path,
+        vec!["package","metadata","android","default_bin"]
+    )
+}
+
+pub const DEFAULT_MIN_SDK_VERSION: i64 = 26;
+
+// The API levels to build for. Target and compile SDK versions default
+// to whatever the SDL template project uses.
+pub struct SdkVersions {
+    pub min: i64,
+    pub target: Option<i64>,
+    pub compile: Option<i64>
+}
+
+pub fn get_android_sdk_versions(manifest_path: &Path)->SdlApkResult<SdkVersions> {
+    let get=|key|get_toml_integer(manifest_path,vec!["package","metadata","android",key]);
+
+    Ok(SdkVersions {
+        min: get("min_sdk_version")?.unwrap_or(DEFAULT_MIN_SDK_VERSION),
+        target: get("target_sdk_version")?,
+        compile: get("compile_sdk_version")?
+    })
+}
+
+// The versionCode and versionName written to build.gradle. Unless set with
+// version_code and version_name, they are derived from the package version.
+pub struct AppVersion {
+    pub code: i64,
+    pub name: String
+}
+
+// Compute a versionCode as major*1000000 + minor*1000 + patch, so that
+// e.g. 1.2.3 becomes 1002003. Pre-release and build metadata are ignored.
+pub fn get_version_code(version: &semver::Version)->SdlApkResult<i64> {
+    let out_of_range=||SdlApkError::BadMetadata(format!(
+        "Can't derive versionCode from version {}, set version_code in [package.metadata.android]",
+        version
+    ));
+
+    if version.minor>=1000 || version.patch>=1000 {
+        return Err(out_of_range());
+    }
+
+    let code=version.major
+        .checked_mul(1_000_000)
+        .and_then(|c|c.checked_add(version.minor*1000+version.patch))
+        .ok_or_else(out_of_range)?;
+
+    if code==0 || code>MAX_VERSION_CODE as u64 {
+        return Err(out_of_range());
+    }
+
+    Ok(code as i64)
+}
+
+// Google Play's maximum versionCode.
+const MAX_VERSION_CODE: i64=2_100_000_000;
+
+pub fn check_version_code(code: i64)->SdlApkResult<i64> {
+    if code<=0 || code>MAX_VERSION_CODE {
+        return Err(SdlApkError::BadMetadata(format!(
+            "version_code {} is out of range, it must be between 1 and {}",
+            code,
+            MAX_VERSION_CODE
+        )));
+    }
+
+    Ok(code)
+}
+
+pub fn get_app_version(package: &ResolvedPackage)->SdlApkResult<AppVersion> {
+    let manifest_path=&package.manifest_path;
+    let code=match get_toml_integer(manifest_path,vec!["package","metadata","android","version_code"])? {
+        Some(code)=>check_version_code(code)?,
+        None=>get_version_code(&package.version)?
+    };
+
+    let name=get_toml_string(manifest_path,vec!["package","metadata","android","version_name"])?
+        .unwrap_or(package.version.to_string());
+
+    Ok(AppVersion {code,name})
+}
+
+// The metadata table to read keys from that can be overridden per example.
+// This is [package.metadata.android.examples.<name>] if it has any of the
+// keys, otherwise [package.metadata.android]. Also returns whether the
+// example table is the one used.
+pub fn get_android_metadata_table<'a>(
+        manifest_path: &Path,
+        example: Option<&'a str>,
+        keys: &[&str]
+    )->SdlApkResult<(Vec<&'a str>,bool)> {
+    let base=vec!["package","metadata","android"];
+    if let Some(name)=example {
+        let mut table=base.clone();
+        table.extend(["examples",name]);
+        for key in keys {
+            let mut path=table.clone();
+            path.push(key);
+            if get_toml_value(manifest_path,path)?.is_some() {
+                return Ok((table,true));
+            }
+        }
+    }
+
+    Ok((base,false))
+}
+
+// The path of a key that can be overridden per example, falling back to
+// [package.metadata.android] if the example doesn't set it. Also returns
+// whether the example sets it.
+pub fn get_android_metadata_key<'a>(
+        manifest_path: &Path,
+        example: Option<&'a str>,
+        key: &'a str
+    )->SdlApkResult<(Vec<&'a str>,bool)> {
+    let (mut path,from_example)=get_android_metadata_table(manifest_path,example,&[key])?;
+    path.push(key);
+    Ok((path,from_example))
+}
+
+// Turn an example name into a valid segment of an application id.
+fn get_app_id_segment(name: &str)->String {
+    let segment:String=name.chars()
+        .map(|c|if c.is_ascii_alphanumeric() {c} else {'_'})
+        .collect();
+
+    match segment.chars().next() {
+        Some(c) if c.is_ascii_alphabetic()=>segment,
+        _=>format!("example_{}",segment)
+    }
+}
+
+// Examples without their own package_name get the example name appended,
+// so that they don't replace each other on the device.
+pub fn get_android_app_id(manifest_path: &Path, example: Option<&str>)->SdlApkResult<String> {
+    if let Some(name)=example {
+        if let Some(app_id)=get_toml_string(manifest_path,
+            vec!["package","metadata","android","examples",name,"package_name"]
+        )? {
+            return Ok(app_id);
+        }
+    }
+
+    let app_id=get_toml_string(manifest_path,
         vec!["package","metadata","android","package_name"]
-    ).unwrap_or("org.libsdl.app".to_string())
+    )?.unwrap_or("org.libsdl.app".to_string());
+
+    Ok(match example {
+        Some(name)=>format!("{}.{}",app_id,get_app_id_segment(name)),
+        None=>app_id
+    })
+}
+
+pub fn get_android_title(manifest_path: &Path, example: Option<&str>)->SdlApkResult<String> {
+    let (path,_)=get_android_metadata_key(manifest_path,example,"title")?;
+    Ok(get_toml_string(manifest_path,path)?.unwrap_or("Untitled".to_string()))
+}
+
+// The template variables available in files of the template_dir.
+fn get_project_template(
+        manifest_path: &Path,
+        sdl: &SdlSources,
+        appid: &str,
+        appname: &str,
+        sdk_versions: &SdkVersions,
+        app_version: &AppVersion
+    )->SdlApkResult<ProjectTemplate> {
+    let mut vars=TemplateVars::new();
+    vars.insert("app_id".to_string(),appid.to_string());
+    vars.insert("title".to_string(),appname.to_string());
+    vars.insert("version_name".to_string(),app_version.name.clone());
+    vars.insert("version_code".to_string(),app_version.code.to_string());
+    vars.insert("min_sdk".to_string(),sdk_versions.min.to_string());
+    if let Some(target)=sdk_versions.target {
+        vars.insert("target_sdk".to_string(),target.to_string());
+    }
+    if let Some(compile)=sdk_versions.compile {
+        vars.insert("compile_sdk".to_string(),compile.to_string());
+    }
+
+    Ok(ProjectTemplate {
+        sdl_dir: sdl.dir.join("android-project"),
+        overlay_dir: get_template_dir(manifest_path)?,
+        vars
+    })
+}
+
+// The directory with the dependencies of a build artifact, where build
+// scripts put the libraries they build.
+fn get_artifact_deps_dir(artifact: &str)->PathBuf {
+    let profile_dir=Path::new(artifact).parent().and_then(|p|p.parent()).unwrap_or(Path::new("."));
+    profile_dir.join("deps")
+}
+
+// Shared libraries the sys crate builds alongside a static SDL, which
+// sdl2-sys does for hidapi on Android.
+fn get_static_sdl_libraries(target_artifacts: &HashMap<String,String>)->Vec<String> {
+    ["hidapi"].iter()
+        .filter(|name|target_artifacts.values().any(|a|get_artifact_deps_dir(a).join(format!("lib{}.so",name)).is_file()))
+        .map(|name|name.to_string())
+        .collect()
 }
 
 fn create_android_project(
-        manifest_path: &Path, 
-        target_artifacts: &HashMap<String,String>) {
-    let manifest_dir=manifest_path.parent().unwrap();
-    let appid=get_android_app_id(manifest_path);
-
-    let appname=get_toml_string(manifest_path,
-        vec!["package","metadata","android","title"]
-    ).unwrap_or("Untitled".to_string());
-
-    // Copy template project from SDL
-    copy_items(
-        &[Path::new(&*get_env_var("SDL")).join("android-project")],
-        Path::new(manifest_dir).join("target"),
-        &CopyOptions::new().skip_exist(true),
-    )
-    .unwrap();
-
-    // Create main activity class
-    let java_main_folder=manifest_dir
-        .join("target/android-project/app/src/main/java")
-        .join(str::replace(&appid, ".", "/"));
-    create_dir_all(java_main_folder.clone()).unwrap();
-    let main_class = "
-		package $APP;
-
-		import org.libsdl.app.SDLActivity;
-
-		public class MainActivity extends SDLActivity {
-		}
-	";
-    let main_class = str::replace(main_class, "$APP", &appid);
-    write(java_main_folder.join("MainActivity.java"), &main_class).expect("Unable to write file");
-
-    // Change project files
-    change_android_project_file(
-        manifest_dir,
-        "app/src/main/AndroidManifest.xml",
-        vec![("SDLActivity", "MainActivity"), ("org.libsdl.app", &*appid)],
-    );
-
-    change_android_project_file(
-        manifest_dir,
-        "app/build.gradle",
-        vec![("org.libsdl.app", &*appid)]
-    );
-
-    change_android_project_file(
-        manifest_dir,
-        "app/src/main/res/values/strings.xml",
-        vec![("Game", &*appname)],
-    );
+        package: &ResolvedPackage, 
+        example: Option<&str>,
+        target_artifacts: &HashMap<String,String>,
+        sdl: &SdlSources,
+        profile: BuildProfile)->SdlApkResult<()> {
+    let manifest_path=&package.manifest_path;
+    let project_dir=&package.android_project_dir();
+    let appid=get_android_app_id(manifest_path,example)?;
+    let appname=get_android_title(manifest_path,example)?;
+
+    let sdk_versions=get_android_sdk_versions(manifest_path)?;
+    let app_version=get_app_version(package)?;
+    let template=get_project_template(manifest_path,sdl,&appid,&appname,&sdk_versions,&app_version)?;
+
+    // Generate project files from the SDL template, and the user template
+    // on top
+    let mut files=template.collect()?;
+    files.remove_dir("app/jni/src");
+
+    files.change("app/src/main/AndroidManifest.xml",|content|{
+        render_android_manifest(content,&appid,&get_manifest_metadata(manifest_path)?)
+    })?;
+
+    files.change("app/src/main/res/values/strings.xml",|content|{
+        render_strings_xml(content,&appname)
+    })?;
+
+    let mut abis:Vec<&str>=vec![];
+    for target in target_artifacts.keys() {
+        abis.push(get_target_android_name(target)?);
+    }
+    abis.sort();
+    let abi_filters=abis.iter().map(|a|format!("'{}'",a)).collect::<Vec<String>>().join(", ");
+
+    let gradle_metadata=get_gradle_metadata(manifest_path)?;
+    files.change("app/build.gradle",|content|{
+        let mut content=content.replace("org.libsdl.app",&appid);
+        content=set_gradle_property(&content,"abiFilters",&abi_filters);
+        content=set_gradle_property(&content,"minSdkVersion",&sdk_versions.min.to_string());
+        if let Some(target)=sdk_versions.target {
+            content=set_gradle_property(&content,"targetSdkVersion",&target.to_string());
+        }
+        if let Some(compile)=sdk_versions.compile {
+            content=set_gradle_property(&content,"compileSdkVersion",&compile.to_string());
+        }
+
+        content=set_app_platform(&content,sdk_versions.min);
+        content=set_gradle_property(&content,"versionCode",&app_version.code.to_string());
+        content=set_gradle_property(&content,"versionName",&gradle_string(&app_version.name));
+        if sdl.static_link {
+            content=set_optional_native_build(&content);
+        }
+        customize_build_gradle(&content,&gradle_metadata)
+    })?;
+
+    // The shared libraries to bundle besides libmain.so. With static SDL,
+    // SDL itself is part of libmain.so.
+    let libraries:Vec<String>=if sdl.static_link {
+        get_static_sdl_libraries(target_artifacts)
+    } else {
+        sdl.libraries.iter().map(|l|l.name.clone()).collect()
+    };
+
+    // Create main activity class and add user java sources. The libraries
+    // to load only differ from SDLActivity's with satellites or static SDL.
+    let mut load_libraries=vec![];
+    if !sdl.static_link {
+        load_libraries.push(sdl.name());
+    }
+    load_libraries.extend(libraries.iter().cloned());
+    load_libraries.push("main".to_string());
+
+    install_java_sources(
+        &mut files,
+        "app/src/main/java",
+        &appid,
+        &get_java_metadata(manifest_path,example)?,
+        (sdl.static_link || !libraries.is_empty()).then_some(&*load_libraries)
+    )?;
+
+    add_icons(
+        &mut files,
+        "app/src/main/res",
+        &get_icon_metadata(manifest_path,example)?
+    )?;
+
+    // Configured assets take precedence over files of the template
+    let assets=collect_assets(&get_assets_metadata(manifest_path,example)?)?;
+    for rel in assets.keys() {
+        files.remove(&format!("app/src/main/assets/{}",path_to_name(rel)));
+    }
+    let template_assets:BTreeSet<PathBuf>=files.dir_files("app/src/main/assets").iter()
+        .map(PathBuf::from)
+        .collect();
+
+    files.sync(project_dir,&sdl.version.to_string())?;
+    sync_assets(&project_dir.join("app/src/main/assets"),&assets,&template_assets)?;
 
     // Remove C sources
     remove_items(&[
-        manifest_dir.join("target/android-project/app/jni/src")
-    ]).unwrap();
+        project_dir.join("app/jni/src")
+    ]).map_err(fs_error("Unable to remove C sources"))?;
+
+    // Link SDL into project, replacing a link to another SDL tree. With
+    // static SDL, there is no native build in the project.
+    let sdl_link=project_dir.join("app/jni/SDL");
+    if read_link(&sdl_link).map(|p|sdl.static_link || p!=sdl.dir).unwrap_or(false) {
+        remove_symlink_dir(&sdl_link).map_err(io_error("Unable to remove old SDL link"))?;
+    }
 
-    // Link SDL into project
-    if !manifest_dir.join("target/android-project/app/jni/SDL").is_dir() {
+    if !sdl.static_link && !sdl_link.is_dir() {
         symlink_dir(
-            Path::new(&*get_env_var("SDL")),
-            manifest_dir.join("target/android-project/app/jni/SDL"),
+            &sdl.dir,
+            &sdl_link,
         )
-        .unwrap();
+        .map_err(io_error("Unable to link SDL into project"))?;
     }
 
-    // Copy libmain.so to all targets
+    // Remove libraries for targets not part of this build
+    let jni_libs_dir=project_dir.join("app/src/main/jniLibs");
+    if jni_libs_dir.is_dir() {
+        for entry in read_dir(&jni_libs_dir).map_err(io_error("Unable to read jniLibs dir"))? {
+            let path=entry.map_err(io_error("Unable to read jniLibs dir"))?.path();
+            let name=path.file_name().map(|n|n.to_string_lossy().to_string()).unwrap_or_default();
+            if !abis.contains(&&*name) {
+                remove_items(&[path]).map_err(fs_error("Unable to remove old libraries"))?;
+            }
+        }
+    }
+
+    // Copy libmain.so and the other libraries to all targets, and remove
+    // libraries no longer used
+    let mut lib_names=vec!["libmain.so".to_string()];
+    lib_names.extend(libraries.iter().map(|name|format!("lib{}.so",name)));
     for (target, artifact) in target_artifacts {
-        let target_android_name=get_target_android_name(target);
+        let target_android_name=get_target_android_name(target)?;
         //println!("{:?}",target);
 
-        let android_dir = manifest_dir
-            .join("target/android-project/app/src/main/jniLibs")
+        let android_dir = project_dir
+            .join("app/src/main/jniLibs")
             .join(target_android_name);
 
-        create_dir_all(&android_dir).unwrap();
+        create_dir_all(&android_dir).map_err(io_error("Unable to create jniLibs dir"))?;
+        for entry in read_dir(&android_dir).map_err(io_error("Unable to read jniLibs dir"))? {
+            let path=entry.map_err(io_error("Unable to read jniLibs dir"))?.path();
+            let name=path.file_name().map(|n|n.to_string_lossy().to_string()).unwrap_or_default();
+            if !lib_names.contains(&name) {
+                remove_items(&[path]).map_err(fs_error("Unable to remove old libraries"))?;
+            }
+        }
+
         copy(
             artifact,
             android_dir.join("libmain.so")
-        ).unwrap();
-    }
-}
-
-fn change_android_project_file(manifest_dir: &Path, file_name: &str, replacements: Vec<(&str, &str)>) {
-    let mut content = read_to_string(Path::new(&*get_env_var("SDL"))
-        .join("android-project")
-        .join(file_name)
-    ).expect("Unable to read manifest file");
-
-    for (from, to) in replacements {
-        content = content.replace(from, to);
+        ).map_err(io_error("Unable to copy libmain.so"))?;
+
+        let lib_dir=if sdl.static_link {
+            get_artifact_deps_dir(artifact)
+        } else {
+            get_sdl_cache_dir(&package.target_dir,profile,target_android_name)
+        };
+        for name in &libraries {
+            let file_name=format!("lib{}.so",name);
+            copy(
+                lib_dir.join(&file_name),
+                android_dir.join(&file_name)
+            ).map_err(io_error(format!("Unable to copy {}",file_name)))?;
+        }
     }
 
-    //println!("{:?}",manifest_dir.join("target/android-project").join(file_name));
-
-    write(
-        manifest_dir.join("target/android-project").join(file_name),
-        &content,
-    )
-    .expect("Unable to write file");
+    Ok(())
 }
 
 pub fn sign_android(
-        manifest_path: &Path, 
-        ks_file: Option<String>,
-        ks_pass: Option<String>
-    ) {
-    let manifest_dir=manifest_path.parent().unwrap();
-    let release_dir=manifest_dir.join("target/android-project/app/build/outputs/apk/release");
+        package: &ResolvedPackage, 
+        signing: &Signing
+    )->SdlApkResult<()> {
+    let release_dir=package.android_project_dir().join("app/build/outputs/apk/release");
     //println!("{:?}",release_dir);
 
     // Find android build tools.
-    let tool_paths=std::fs::read_dir(Path::new(&*get_env_var("ANDROID_HOME")).join("build-tools")).unwrap();
-    let mut tool_paths:Vec<String>=tool_paths.map(|d|{
-        d.unwrap().path().file_name().unwrap()
-            .to_os_string().into_string().unwrap()
-    }).collect();
+    let build_tools_dir=Path::new(&*get_env_var("ANDROID_HOME")?).join("build-tools");
+    let mut tool_paths:Vec<String>=vec![];
+    for d in read_dir(&build_tools_dir).map_err(io_error("Unable to find Android build-tools"))? {
+        let d=d.map_err(io_error("Unable to find Android build-tools"))?;
+        tool_paths.push(d.file_name().to_string_lossy().to_string());
+    }
     tool_paths.sort();
     tool_paths.reverse();
-    let tools_version=tool_paths[0].clone();
+    let tools_version=match tool_paths.first() {
+        Some(v)=>v.clone(),
+        None=>return Err(SdlApkError::ToolNotFound(build_tools_dir.join("*").display().to_string()))
+    };
     println!("Using build-tools: {}",tools_version);
 
     // Determine key file. Generate if needed.
-    let (key_file,key_pass)=if ks_file.is_some() {
+    let (key_file,key_pass)=if let Signing::Keystore{file,pass}=signing {
         (
-            ks_file.unwrap(),
-            ks_pass.expect("Need keystore password")
+            path_to_string(file)?,
+            pass.clone()
         )
     } else {
         let key_path=release_dir.join("app-release.jks");
         if !key_path.exists() {
             println!("Generating keyfile...");
-            assert!(Command::new("keytool")
+            run_command(Command::new("keytool")
                 .arg("-genkey")
                 .arg("-dname").arg("CN=Unknown, OU=Unknown, O=Unknown, L=Unknown, S=Unknown, C=Unknown")
                 .arg("-storepass").arg("android")
@@ -198,13 +459,11 @@ pub fn sign_android(
                 .arg("-keyalg").arg("RSA")
                 .arg("-keysize").arg("2048")
                 .arg("-validity").arg("10000")
-                .status()
-                .unwrap()
-                .success());
+            )?;
         }
 
         (
-            key_path.into_os_string().into_string().unwrap(),
+            path_to_string(&key_path)?,
             "pass:android".to_string()
         )
     };
@@ -212,61 +471,114 @@ pub fn sign_android(
     println!("Using keyfile: {}",key_file);
 
     // Run zipalign.
-    let zipalign_path=Path::new(&*get_env_var("ANDROID_HOME"))
+    let zipalign_path=Path::new(&*get_env_var("ANDROID_HOME")?)
         .join("build-tools").join(tools_version.clone()).join("zipalign");
 
-    assert!(Command::new(zipalign_path)
+    run_command(Command::new(zipalign_path)
         .arg("-v").arg("-f")
         .arg("-p").arg("4")
         .arg(release_dir.join("app-release-unsigned.apk"))
         .arg(release_dir.join("app-release-unsigned-aligned.apk"))
-        .status()
-        .unwrap()
-        .success());
+    )?;
 
     // Run apksigner
-    let apksigner_path=Path::new(&*get_env_var("ANDROID_HOME"))
+    let apksigner_path=Path::new(&*get_env_var("ANDROID_HOME")?)
         .join("build-tools").join(tools_version.clone()).join("apksigner");
 
-    assert!(Command::new(apksigner_path)
+    run_command(Command::new(apksigner_path)
         .arg("sign")
         .arg("-ks").arg(key_file)
         .arg("-ks-pass").arg(key_pass)
         .arg("-out").arg(release_dir.join("app-release.apk"))
         .arg(release_dir.join("app-release-unsigned-aligned.apk"))
-        .status()
-        .unwrap()
-        .success());
+    )
 }
 
 // keytool -android blabla -genkey -v -keystore my-release-key.jks -keyalg RSA -keysize 2048 -validity 10000 -alias my-alias
 // /home/micke/Android/Sdk/build-tools/30.0.3/zipalign -v -p 4 app-release-unsigned.apk app-release-unsigned-aligned.apk
 // /home/micke/Android/Sdk/build-tools/30.0.3/apksigner sign -ks my-release-key.jks -ks-pass pass:android -out app-release.apk app-release-unsigned-aligned.apk
 
+pub fn get_apk_path(package: &ResolvedPackage, profile:BuildProfile, signed:bool)->PathBuf {
+    let output_apk=match (profile,signed) {
+        (BuildProfile::Debug,_)=>"debug/app-debug.apk",
+        (BuildProfile::Release,true)=>"release/app-release.apk",
+        (BuildProfile::Release,false)=>"release/app-release-unsigned.apk",
+    };
+
+    package.android_project_dir()
+        .join("app/build/outputs/apk/")
+        .join(output_apk)
+}
+
 pub fn build_android_project(
-        manifest_path: &Path, 
+        package: &ResolvedPackage, 
+        example: Option<&str>,
         target_artifacts: &HashMap<String,String>,
+        sdl: &SdlSources,
         profile:BuildProfile,
-        ks_file: Option<String>,
-        ks_pass: Option<String>
-    ) {
-    let manifest_dir=manifest_path.parent().unwrap();
-
-    create_android_project(manifest_path,target_artifacts);
+        signing: Option<&Signing>
+    )->SdlApkResult<()> {
+    create_android_project(package,example,target_artifacts,sdl,profile)?;
 
     let gradle_task=match profile {
         BuildProfile::Debug=>"assembleDebug",
         BuildProfile::Release=>"assembleRelease",
     };
 
-    assert!(Command::new("./gradlew")
-        .args([gradle_task])
-        .current_dir(manifest_dir.join("./target/android-project"))
-        .status()
-        .unwrap()
-        .success());
+    // Static SDL is linked into libmain.so, so skip the project's ndk-build
+    let mut gradle_command=Command::new("./gradlew");
+    gradle_command.args([gradle_task]);
+    if sdl.static_link {
+        gradle_command.arg("-PEXCLUDE_NATIVE_LIBS");
+    }
+
+    run_command(gradle_command.current_dir(package.android_project_dir()))?;
+
+    if let (BuildProfile::Release,Some(signing))=(profile,signing) {
+        sign_android(package,signing)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use semver::Version;
+
+    #[test]
+    fn version_code_formula() {
+        assert_eq!(get_version_code(&Version::new(1,2,3)).unwrap(),1002003);
+        assert_eq!(get_version_code(&Version::new(0,1,0)).unwrap(),1000);
+        assert_eq!(get_version_code(&Version::new(0,0,1)).unwrap(),1);
+        assert_eq!(get_version_code(&Version::new(2100,0,0)).unwrap(),2_100_000_000);
+        assert_eq!(get_version_code(&Version::parse("1.2.3-beta.1+build.5").unwrap()).unwrap(),1002003);
+    }
+
+    #[test]
+    fn version_code_out_of_range() {
+        for version in ["0.0.0","1.1000.0","1.0.1000","2100.0.1","99999999999999.0.0"] {
+            assert!(
+                matches!(get_version_code(&Version::parse(version).unwrap()),Err(SdlApkError::BadMetadata(_))),
+                "{}",version
+            );
+        }
+    }
+
+    #[test]
+    fn version_code_override_range() {
+        assert_eq!(check_version_code(1).unwrap(),1);
+        assert_eq!(check_version_code(2_100_000_000).unwrap(),2_100_000_000);
+        for code in [0,-1,2_100_000_001,i64::MAX] {
+            assert!(matches!(check_version_code(code),Err(SdlApkError::BadMetadata(_))),"{}",code);
+        }
+    }
 
-    if matches!(profile,BuildProfile::Release) {
-        sign_android(manifest_path,ks_file,ks_pass);
+    #[test]
+    fn app_id_segments() {
+        assert_eq!(get_app_id_segment("particles"),"particles");
+        assert_eq!(get_app_id_segment("hello-world"),"hello_world");
+        assert_eq!(get_app_id_segment("3d"),"example_3d");
+        assert_eq!(get_app_id_segment("_x"),"example__x");
     }
 }
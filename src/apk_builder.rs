@@ -0,0 +1,320 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::fs::canonicalize;
+use crate::build_bin_as_lib::*;
+use crate::android_project::*;
+use crate::util::*;
+use crate::error::*;
+use crate::workspace::*;
+use crate::clean::*;
+use crate::sdl::*;
+use crate::{BuildProfile, SUPPORTED_TARGETS};
+
+/// How to sign release builds.
+#[derive(Clone,Debug)]
+pub enum Signing {
+    /// Generate a self signed key with the password "android".
+    SelfSigned,
+
+    /// Sign with a key from a keystore. The pass is given in apksigner
+    /// format, e.g. "pass:secret".
+    Keystore {
+        file: PathBuf,
+        pass: String
+    }
+}
+
+/// A native library built for one ABI.
+#[derive(Clone,Debug)]
+pub struct AbiArtifact {
+    pub target: String,
+    pub abi: String,
+    pub lib_path: PathBuf
+}
+
+/// The result of a successful build.
+#[derive(Clone,Debug)]
+pub struct ApkOutput {
+    pub apk_path: PathBuf,
+    pub app_id: String,
+    pub artifacts: Vec<AbiArtifact>
+}
+
+/// Builds an APK from a crate using SDL.
+pub struct ApkBuilder {
+    manifest_path: PathBuf,
+    build_target: BuildTarget,
+    package: Option<String>,
+    target_dir: Option<PathBuf>,
+    targets: Vec<String>,
+    profile: BuildProfile,
+    cargo_profile: Option<String>,
+    sign: bool,
+    signing: Signing,
+    rebuild_sdl: bool,
+    allow_sdl_mismatch: bool,
+    features: FeatureSelection
+}
+
+impl ApkBuilder {
+    pub fn new<P: Into<PathBuf>>(manifest_path: P)->Self {
+        Self {
+            manifest_path: manifest_path.into(),
+            build_target: BuildTarget::Bin,
+            package: None,
+            target_dir: None,
+            targets: vec![],
+            profile: BuildProfile::Debug,
+            cargo_profile: None,
+            sign: true,
+            signing: Signing::SelfSigned,
+            rebuild_sdl: false,
+            allow_sdl_mismatch: false,
+            features: FeatureSelection::default()
+        }
+    }
+
+    /// Which crate target to package. Defaults to the only bin target, or
+    /// default_bin from [package.metadata.android].
+    pub fn build_target(mut self, build_target: BuildTarget)->Self {
+        self.build_target=build_target;
+        self
+    }
+
+    /// The workspace member to package. Defaults to the package at the
+    /// manifest path.
+    pub fn package<S: Into<String>>(mut self, package: S)->Self {
+        self.package=Some(package.into());
+        self
+    }
+
+    /// Directory for all generated artifacts. Defaults to CARGO_TARGET_DIR,
+    /// build.target-dir from the cargo config, or the workspace target dir.
+    pub fn target_dir<P: Into<PathBuf>>(mut self, target_dir: P)->Self {
+        self.target_dir=Some(target_dir.into());
+        self
+    }
+
+    /// The Rust targets to build for. If empty, build_targets from
+    /// [package.metadata.android] or all supported targets are used.
+    pub fn targets<I, S>(mut self, targets: I)->Self
+            where I: IntoIterator<Item=S>, S: Into<String> {
+        self.targets=targets.into_iter().map(|s|s.into()).collect();
+        self
+    }
+
+    /// The gradle variant to build.
+    pub fn profile(mut self, profile: BuildProfile)->Self {
+        self.profile=profile;
+        self
+    }
+
+    /// The cargo profile to build with, e.g. a custom profile from
+    /// Cargo.toml. Defaults to dev or release depending on the profile.
+    pub fn cargo_profile<S: Into<String>>(mut self, cargo_profile: S)->Self {
+        self.cargo_profile=Some(cargo_profile.into());
+        self
+    }
+
+    /// Whether to sign release builds. Defaults to true.
+    pub fn sign(mut self, sign: bool)->Self {
+        self.sign=sign;
+        self
+    }
+
+    /// How to sign release builds. Debug builds are signed by gradle.
+    pub fn signing(mut self, signing: Signing)->Self {
+        self.signing=signing;
+        self
+    }
+
+    /// Build SDL even if the cached build is up to date.
+    pub fn rebuild_sdl(mut self, rebuild_sdl: bool)->Self {
+        self.rebuild_sdl=rebuild_sdl;
+        self
+    }
+
+    /// Build even if the major version of the SDL sources differs from the
+    /// one the sdl2-sys crate is made for.
+    pub fn allow_sdl_mismatch(mut self, allow_sdl_mismatch: bool)->Self {
+        self.allow_sdl_mismatch=allow_sdl_mismatch;
+        self
+    }
+
+    /// Cargo features to enable, in addition to the features listed in
+    /// [package.metadata.android].
+    pub fn features<I, S>(mut self, features: I)->Self
+            where I: IntoIterator<Item=S>, S: Into<String> {
+        self.features.features=features.into_iter().map(|s|s.into()).collect();
+        self
+    }
+
+    /// Activate all features of the package.
+    pub fn all_features(mut self, all_features: bool)->Self {
+        self.features.all_features=all_features;
+        self
+    }
+
+    /// Don't activate the default feature of the package.
+    pub fn no_default_features(mut self, no_default_features: bool)->Self {
+        self.features.no_default_features=no_default_features;
+        self
+    }
+
+    fn get_features(&self, manifest_path: &Path)->SdlApkResult<FeatureSelection> {
+        let mut features=self.features.clone();
+        features.features.extend(get_android_features(manifest_path)?);
+        Ok(features)
+    }
+
+    fn resolve_package(&self)->SdlApkResult<ResolvedPackage> {
+        let manifest_path=canonicalize(&self.manifest_path)
+            .map_err(io_error(format!("Unable to find {}",self.manifest_path.display())))?;
+
+        resolve_package(&manifest_path,self.package.as_deref(),self.target_dir.as_deref())
+    }
+
+    fn get_targets(&self, manifest_path: &Path)->SdlApkResult<Vec<String>> {
+        let targets=if !self.targets.is_empty() {
+            self.targets.clone()
+        } else if let Some(targets)=get_android_build_targets(manifest_path)? {
+            targets
+        } else {
+            SUPPORTED_TARGETS.iter().map(|s|s.to_string()).collect()
+        };
+
+        for t in &targets {
+            if !SUPPORTED_TARGETS.contains(&&**t) {
+                return Err(SdlApkError::UnsupportedTarget(t.clone()));
+            }
+        }
+
+        Ok(targets)
+    }
+
+    pub fn build(&self)->SdlApkResult<ApkOutput> {
+        for k in &["ANDROID_HOME", "ANDROID_NDK_HOME"] {
+            get_env_var(k)?;
+        }
+
+        let package=self.resolve_package()?;
+        let manifest_path=&package.manifest_path;
+        let targets=self.get_targets(manifest_path)?;
+        let sdk_versions=get_android_sdk_versions(manifest_path)?;
+        let features=self.get_features(manifest_path)?;
+        let build_target=resolve_build_target(
+            &package,
+            self.build_target.clone(),
+            get_android_default_bin(manifest_path)?
+        )?;
+
+        let cargo_profile=self.cargo_profile.clone()
+            .unwrap_or(self.profile.default_cargo_profile().to_string());
+        let signing=if self.sign {Some(&self.signing)} else {None};
+        let sdl=get_sdl_sources(&package)?;
+        check_sdl_version(&package,&sdl,self.allow_sdl_mismatch)?;
+        let example=match &build_target {
+            BuildTarget::Example(name)=>Some(name.clone()),
+            _=>None
+        };
+
+        // With static SDL, the sys crate builds SDL using the NDK toolchain
+        // from the env.
+        let build_env=if sdl.static_link {
+            get_static_build_env(&targets,sdk_versions.min,&package.target_dir)?
+        } else {
+            build_sdl_for_android(
+                &targets,
+                &sdl,
+                self.profile,
+                get_profile_dir_name(&cargo_profile),
+                &sdk_versions,
+                &package.target_dir,
+                self.rebuild_sdl
+            )?;
+            vec![]
+        };
+        let target_artifacts=build_bin_as_lib(
+            &package,
+            build_target,
+            &targets,
+            &cargo_profile,
+            sdk_versions.min,
+            &features,
+            &build_env
+        )?;
+        build_android_project(
+            &package,
+            example.as_deref(),
+            &target_artifacts,
+            &sdl,
+            self.profile,
+            signing
+        )?;
+
+        let mut artifacts=vec![];
+        for (target,lib_path) in target_artifacts {
+            artifacts.push(AbiArtifact {
+                abi: get_target_android_name(&target)?.to_string(),
+                target,
+                lib_path: PathBuf::from(lib_path)
+            });
+        }
+        artifacts.sort_by(|a,b|a.target.cmp(&b.target));
+
+        Ok(ApkOutput {
+            apk_path: get_apk_path(&package,self.profile,self.sign),
+            app_id: get_android_app_id(manifest_path,example.as_deref())?,
+            artifacts
+        })
+    }
+
+    /// Sign a previously built release APK.
+    pub fn sign_apk(&self)->SdlApkResult<()> {
+        sign_android(&self.resolve_package()?,&self.signing)
+    }
+
+    /// Remove build artifacts of the package.
+    pub fn clean(&self, options: &CleanOptions)->SdlApkResult<()> {
+        clean(&self.resolve_package()?,options)
+    }
+}
+
+/// Install a built APK on the connected device, start it, and follow its log.
+pub fn run_apk(output: &ApkOutput)->SdlApkResult<()> {
+    let appid=&output.app_id;
+    let p = Path::new(&*get_env_var("ANDROID_HOME")?).join("platform-tools/adb");
+    run_command(Command::new(p.clone())
+        .args(["-d", "install", "-r"])
+        .arg(&output.apk_path)
+    )?;
+
+    run_command(Command::new(p.clone())
+        .args(["shell", "am", "force-stop", appid])
+    )?;
+
+    let mut activity = appid.clone();
+    activity.push_str("/.MainActivity");
+
+    run_command(Command::new(p.clone())
+        .args(["shell", "am", "start", "-W", "-n", &*activity])
+    )?;
+
+    let pid_vec = run_command_output(Command::new(p.clone())
+        .arg("shell")
+        .arg("pidof")
+        .arg(appid)
+    )?.stdout;
+
+    let pid = String::from_utf8_lossy(&pid_vec);
+    let pid: u32 = pid
+        .trim()
+        .parse()
+        .map_err(|_|SdlApkError::Launch(format!("Unable to get pid for {}",appid)))?;
+
+    println!("Launched with PID: {}", pid);
+
+    run_command(Command::new(p.clone())
+        .args(["logcat","-v","color","--pid",&*pid.to_string()])
+    )
+}
@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{copy, create_dir_all, metadata, remove_dir, remove_file};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use toml::value::Value;
+use walkdir::WalkDir;
+use crate::util::*;
+use crate::error::*;
+use crate::android_project::*;
+
+// The asset keys in [package.metadata.android]. Each can be overridden per
+// example. Directories are relative to the package manifest, globs to each
+// asset directory.
+#[derive(Clone,Debug,Default)]
+pub struct AssetsMetadata {
+    pub dirs: Vec<PathBuf>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>
+}
+
+pub fn get_assets_metadata(manifest_path: &Path, example: Option<&str>)->SdlApkResult<AssetsMetadata> {
+    let manifest_dir=manifest_path.parent().unwrap_or(Path::new("."));
+    let path=|key|get_android_metadata_key(manifest_path,example,key).map(|(path,_)|path);
+    let dirs=match get_toml_value(manifest_path,path("assets")?)? {
+        None=>vec![],
+        Some(Value::String(s))=>vec![s],
+        Some(_)=>get_toml_string_array(manifest_path,path("assets")?)?
+            .unwrap_or_default()
+    };
+
+    Ok(AssetsMetadata {
+        dirs: dirs.iter().map(|d|manifest_dir.join(d)).collect(),
+        include: get_toml_string_array(manifest_path,path("assets_include")?)?
+            .unwrap_or_default(),
+        exclude: get_toml_string_array(manifest_path,path("assets_exclude")?)?
+            .unwrap_or_default()
+    })
+}
+
+fn build_glob_set(globs: &[String])->SdlApkResult<GlobSet> {
+    let mut builder=GlobSetBuilder::new();
+    for g in globs {
+        builder.add(Glob::new(g).map_err(|e|SdlApkError::BadMetadata(format!("Bad glob {}: {}",g,e)))?);
+    }
+
+    builder.build().map_err(|e|SdlApkError::BadMetadata(e.to_string()))
+}
+
+// Find all asset files, keyed by their path inside the assets folder.
+pub fn collect_assets(metadata: &AssetsMetadata)->SdlApkResult<BTreeMap<PathBuf,PathBuf>> {
+    let include=build_glob_set(&metadata.include)?;
+    let exclude=build_glob_set(&metadata.exclude)?;
+    let mut assets:BTreeMap<PathBuf,PathBuf>=BTreeMap::new();
+
+    for dir in &metadata.dirs {
+        if !dir.is_dir() {
+            return Err(SdlApkError::BadMetadata(format!("Assets dir not found: {}",dir.display())));
+        }
+
+        for entry in WalkDir::new(dir).follow_links(true) {
+            let entry=entry.map_err(walkdir_error(format!("Unable to read {}",dir.display())))?;
+
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let rel=entry.path().strip_prefix(dir).unwrap_or(entry.path()).to_path_buf();
+            if (!metadata.include.is_empty() && !include.is_match(&rel))
+                    || exclude.is_match(&rel) {
+                continue;
+            }
+
+            if let Some(other)=assets.get(&rel) {
+                return Err(SdlApkError::BadMetadata(format!(
+                    "Asset {} found in both {} and {}",
+                    rel.display(),other.display(),entry.path().display()
+                )));
+            }
+
+            assets.insert(rel,entry.path().to_path_buf());
+        }
+    }
+
+    Ok(assets)
+}
+
+fn needs_copy(src: &Path, dest: &Path)->bool {
+    match (metadata(src),metadata(dest)) {
+        (Ok(s),Ok(d))=>{
+            s.len()!=d.len() || match (s.modified(),d.modified()) {
+                (Ok(s),Ok(d))=>s>d,
+                _=>true
+            }
+        },
+        _=>true
+    }
+}
+
+// Make the assets dir of the Android project mirror the configured asset
+// files. Only changed files are copied, and all other files are removed,
+// except the ones from the project template.
+pub fn sync_assets(
+        assets_dir: &Path,
+        assets: &BTreeMap<PathBuf,PathBuf>,
+        template_files: &BTreeSet<PathBuf>
+    )->SdlApkResult<()> {
+    for (rel,src) in assets {
+        let dest=assets_dir.join(rel);
+        if needs_copy(src,&dest) {
+            if let Some(parent)=dest.parent() {
+                create_dir_all(parent).map_err(io_error("Unable to create assets dir"))?;
+            }
+            copy(src,&dest).map_err(io_error(format!("Unable to copy asset {}",src.display())))?;
+        }
+    }
+
+    if !assets_dir.is_dir() {
+        return Ok(());
+    }
+
+    // Remove stale files, then directories left empty, deepest first.
+    let mut stale_dirs=vec![];
+    for entry in WalkDir::new(assets_dir).min_depth(1).contents_first(true) {
+        let entry=entry.map_err(walkdir_error(format!("Unable to read {}",assets_dir.display())))?;
+
+        let rel=entry.path().strip_prefix(assets_dir).unwrap_or(entry.path());
+        if entry.file_type().is_dir() {
+            stale_dirs.push(entry.path().to_path_buf());
+        }
+
+        else if !assets.contains_key(rel) && !template_files.contains(rel) {
+            remove_file(entry.path()).map_err(io_error(format!("Unable to remove {}",entry.path().display())))?;
+        }
+    }
+
+    for dir in stale_dirs {
+        // Fails if the dir still has files, which is fine.
+        let _=remove_dir(dir);
+    }
+
+    Ok(())
+}
@@ -6,19 +6,29 @@ use cargo::core::{Workspace, Target, PackageId, TargetKind};
 use cargo::core::resolver::CliFeatures;
 use cargo::core::compiler::{Executor, BuildConfig, CompileMode, CompileTarget, CompileKind};
 use cargo::ops::{FilterRule, LibRule, CompileOptions, CompileFilter, Packages};
-use cargo::util::Config as CargoConfig;
 use cargo_util::{ProcessBuilder};
 use std::sync::Mutex;
 use crate::util::*;
-use crate::BuildProfile;
-
-fn get_target_linker(rust_target_name: &str)->&str {
-    match rust_target_name {
-        "aarch64-linux-android"=>"toolchains/llvm/prebuilt/linux-x86_64/bin/aarch64-linux-android26-clang",
-        "armv7-linux-androideabi"=>"toolchains/llvm/prebuilt/linux-x86_64/bin/armv7a-linux-androideabi26-clang",
-        "i686-linux-android"=>"toolchains/llvm/prebuilt/linux-x86_64/bin/i686-linux-android26-clang",
-        _=>{panic!("Unknown target: {}",rust_target_name)}
-    }
+use crate::error::*;
+use crate::workspace::*;
+
+pub fn get_target_clang_triple(rust_target_name: &str)->SdlApkResult<&str> {
+    let triple=match rust_target_name {
+        "aarch64-linux-android"=>"aarch64-linux-android",
+        "armv7-linux-androideabi"=>"armv7a-linux-androideabi",
+        "i686-linux-android"=>"i686-linux-android",
+        "x86_64-linux-android"=>"x86_64-linux-android",
+        _=>{return Err(SdlApkError::UnsupportedTarget(rust_target_name.to_string()))}
+    };
+
+    Ok(triple)
+}
+
+pub fn get_target_linker(rust_target_name: &str, api_level: i64)->SdlApkResult<String> {
+    Ok(format!("toolchains/llvm/prebuilt/linux-x86_64/bin/{}{}-clang",
+        get_target_clang_triple(rust_target_name)?,
+        api_level
+    ))
 }
 
 pub struct LibExecutor {
@@ -47,7 +57,9 @@ impl Executor for LibExecutor {
     ) -> CargoResult<()> {
         if mode == CompileMode::Build
                 && (target.kind() == &TargetKind::Bin || target.kind() == &TargetKind::ExampleBin) {
-            let args:Vec<&str>=cmd.get_args().map(|s|s.to_str().unwrap()).collect();
+            let args:Vec<&str>=cmd.get_args()
+                .map(|s|s.to_str().ok_or_else(||anyhow::format_err!("Non UTF-8 compiler argument")))
+                .collect::<CargoResult<_>>()?;
             let mut new_args=vec![];
             let mut i=0;
             let mut out_dir="".to_string();
@@ -80,8 +92,10 @@ impl Executor for LibExecutor {
                 }
             }
 
+            let linker=self.linkers.get(&target_triple)
+                .ok_or_else(||anyhow::format_err!("No linker for target: {}",target_triple))?;
             let mut linker_arg="linker=".to_string();
-            linker_arg.push_str(&*self.linkers.get(&target_triple).unwrap());
+            linker_arg.push_str(linker);
 
             new_args.push("-C");
             new_args.push(&*linker_arg);
@@ -94,12 +108,16 @@ impl Executor for LibExecutor {
                 .map(drop)?;
 
             let stdout=cmd.arg("--print").arg("file-names").exec_with_output()?;
-            let stdout=String::from_utf8(stdout.stdout).unwrap();
-            let stdout=stdout.lines().next().unwrap();
-            let p=Path::new(&*out_dir).join(stdout.clone());
-            let p=p.into_os_string().into_string().unwrap();
+            let stdout=String::from_utf8(stdout.stdout)?;
+            let stdout=stdout.lines().next()
+                .ok_or_else(||anyhow::format_err!("Unable to determine output file name"))?;
+            let p=Path::new(&*out_dir).join(stdout);
+            let p=p.into_os_string().into_string()
+                .map_err(|_|anyhow::format_err!("Non UTF-8 output path"))?;
 
-            self.out.lock().unwrap().insert(target_triple,p);
+            self.out.lock()
+                .map_err(|_|SdlApkError::Cargo(anyhow::format_err!("Output map poisoned")))?
+                .insert(target_triple,p);
         }
 
         else {
@@ -111,41 +129,96 @@ impl Executor for LibExecutor {
     }
 }
 
+// Cargo features to enable, as given to cargo build.
+#[derive(Clone,Debug,Default)]
+pub struct FeatureSelection {
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool
+}
+
+#[derive(Clone,Debug)]
 pub enum BuildTarget {
     Bin,
+    NamedBin(String),
     Example(String)
 }
 
+// Decide which bin to build when none was named. A crate with several
+// bins needs --bin or a default_bin in the metadata.
+pub fn resolve_build_target(
+        package:&ResolvedPackage,
+        build_target:BuildTarget,
+        default_bin:Option<String>
+    )->SdlApkResult<BuildTarget> {
+    match build_target {
+        BuildTarget::Bin=>{
+            if let Some(name)=default_bin {
+                if !package.bins.contains(&name) {
+                    return Err(SdlApkError::BadMetadata(format!("default_bin {} is not a bin target of {}",name,package.name)));
+                }
+
+                return Ok(BuildTarget::NamedBin(name));
+            }
+
+            match package.bins.len() {
+                0=>Err(SdlApkError::Usage(format!("Package {} has no bin target",package.name))),
+                1=>Ok(BuildTarget::NamedBin(package.bins[0].clone())),
+                _=>Err(SdlApkError::Usage(format!(
+                    "Package {} has several bin targets ({}), use --bin or default_bin in [package.metadata.android] to select one",
+                    package.name,
+                    package.bins.join(", ")
+                )))
+            }
+        },
+        BuildTarget::NamedBin(name)=>{
+            if !package.bins.contains(&name) {
+                return Err(SdlApkError::Usage(format!("No bin target named {} in {}",name,package.name)));
+            }
+
+            Ok(BuildTarget::NamedBin(name))
+        },
+        BuildTarget::Example(name)=>Ok(BuildTarget::Example(name))
+    }
+}
+
+// The directory name cargo uses for a profile's output under target/<triple>.
+pub fn get_profile_dir_name(cargo_profile: &str)->&str {
+    match cargo_profile {
+        "dev" | "test"=>"debug",
+        "bench"=>"release",
+        _=>cargo_profile
+    }
+}
+
 pub fn build_bin_as_lib(
-        manifest_path:&Path,
+        package:&ResolvedPackage,
         build_target: BuildTarget,
-        targets:&Vec<&str>,
-        profile:BuildProfile
-    )->HashMap<String,String> {
+        targets:&[String],
+        cargo_profile:&str,
+        api_level:i64,
+        features:&FeatureSelection,
+        env:&[(String,String)]
+    )->SdlApkResult<HashMap<String,String>> {
+    // The target must already be resolved by resolve_build_target, building
+    // every bin would give one library per bin and no way to pick the app.
+    let (bins,examples)=match build_target {
+        BuildTarget::Bin=>return Err(SdlApkError::Usage(format!("No bin target selected in {}",package.name))),
+        BuildTarget::NamedBin(s)=>(vec![s],vec![]),
+        BuildTarget::Example(s)=>(vec![],vec![s])
+    };
+
     let mut linkers:HashMap<String,String>=HashMap::new();
     for t in targets {
         linkers.insert(
             t.to_string(),
-            Path::new(&*get_env_var("ANDROID_NDK_HOME"))
-                .join(get_target_linker(t))
-                .into_os_string().into_string().unwrap()
+            path_to_string(&Path::new(&*get_env_var("ANDROID_NDK_HOME")?)
+                .join(get_target_linker(t,api_level)?))?
         );
     }
 
-    let mut cargo_config = CargoConfig::default().unwrap();
-    cargo_config.configure(
-    	0, // verbose
-    	false, // quiet
-    	None, // color
-    	cargo_config.frozen(), // frozen
-    	cargo_config.locked(), // locked
-    	cargo_config.offline(), // offline
-    	&None, // target dir
-    	&[], // unstable flags
-    	&[] // cli config
-    ).unwrap();
-
-    let workspace = Workspace::new(manifest_path, &cargo_config).unwrap();
+    let cargo_config = create_cargo_config(Some(&package.target_dir),env)?;
+    let workspace = Workspace::new(&package.manifest_path, &cargo_config).map_err(SdlApkError::Workspace)?;
 
     let mut build_config=BuildConfig::new(
     	&cargo_config,
@@ -153,29 +226,29 @@ pub fn build_bin_as_lib(
     	false,
     	&[],
     	CompileMode::Build
-    ).unwrap();
+    ).map_err(SdlApkError::Workspace)?;
 
-    build_config.requested_profile=profile.to_string().into();
+    build_config.requested_profile=cargo_profile.into();
 
     build_config.requested_kinds=targets.iter().map(|s|{
-        CompileKind::Target(CompileTarget::new(s).unwrap())
-    }).collect();
+        CompileTarget::new(s)
+            .map(CompileKind::Target)
+            .map_err(|_|SdlApkError::UnsupportedTarget(s.to_string()))
+    }).collect::<SdlApkResult<_>>()?;
 
     let compile_options=CompileOptions {
-        build_config: build_config,
-        cli_features: CliFeatures::new_all(false),
-        spec: Packages::Packages(Vec::new()),
+        build_config,
+        cli_features: CliFeatures::from_command_line(
+            &features.features,
+            features.all_features,
+            !features.no_default_features
+        ).map_err(|e|SdlApkError::Usage(format!("{:#}",e)))?,
+        spec: Packages::Packages(vec![package.name.clone()]),
         filter: CompileFilter::Only {
         	all_targets: false,
         	lib: LibRule::False,
-            bins: match build_target {
-                BuildTarget::Bin=>FilterRule::All,
-                BuildTarget::Example(_)=>FilterRule::Just(vec![])
-            },
-        	examples: match build_target {
-                BuildTarget::Bin=>FilterRule::Just(vec![]),
-                BuildTarget::Example(s)=>FilterRule::Just(vec![s])
-            },
+            bins: FilterRule::Just(bins),
+        	examples: FilterRule::Just(examples),
         	tests: FilterRule::Just(vec![]),
         	benches: FilterRule::Just(vec![]),
         },
@@ -188,8 +261,10 @@ pub fn build_bin_as_lib(
 
     let executor=Arc::new(LibExecutor::new(linkers));
     let executor_dyn:Arc<dyn Executor>=executor.clone();
-    cargo::ops::compile_with_exec(&workspace,&compile_options,&executor_dyn).unwrap();
+    cargo::ops::compile_with_exec(&workspace,&compile_options,&executor_dyn)
+        .map_err(SdlApkError::Cargo)?;
 
-    let out=&*executor.out.lock().unwrap();
-    out.clone()
+    let out=executor.out.lock()
+        .map_err(|_|SdlApkError::Cargo(anyhow::format_err!("Output map poisoned")))?;
+    Ok(out.clone())
 }
\ No newline at end of file
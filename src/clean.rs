@@ -0,0 +1,114 @@
+use fs_extra::remove_items;
+use std::path::{Path, PathBuf};
+use std::fs::read_dir;
+use crate::util::*;
+use crate::error::*;
+use crate::workspace::*;
+use crate::SUPPORTED_TARGETS;
+
+/// What to remove with ApkBuilder::clean.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct CleanOptions {
+    /// The generated gradle project.
+    pub project: bool,
+
+    /// SDL libraries copied next to the Rust artifacts, and the jniLibs of
+    /// the gradle project.
+    pub native_libs: bool,
+
+    /// The SDL build cache and static SDL toolchain files, and the libs and
+    /// obj dirs left by ndk-build in the SDL source tree.
+    pub sdl: bool,
+
+    /// Cargo's output for the Android targets. Host artifacts are never
+    /// removed.
+    pub cargo: bool
+}
+
+impl CleanOptions {
+    /// Everything generated by cargo-sdl-apk, but not cargo's own output.
+    pub fn all()->Self {
+        Self {
+            project: true,
+            native_libs: true,
+            sdl: true,
+            cargo: false
+        }
+    }
+}
+
+fn remove_paths(paths: Vec<PathBuf>)->SdlApkResult<()> {
+    for path in paths {
+        if path.exists() {
+            println!("Removing {}",path.display());
+            remove_items(&[&path]).map_err(fs_error(format!("Unable to remove {}",path.display())))?;
+        }
+    }
+
+    Ok(())
+}
+
+// The SDL libraries, including the satellite libraries, under
+// target/<triple>/<profile>/deps for all profiles.
+fn find_copied_libs(target_dir: &Path)->SdlApkResult<Vec<PathBuf>> {
+    let mut libs=vec![];
+    for target in SUPPORTED_TARGETS {
+        let dir=target_dir.join(target);
+        if !dir.is_dir() {
+            continue;
+        }
+
+        for entry in read_dir(&dir).map_err(io_error(format!("Unable to read {}",dir.display())))? {
+            let deps_dir=entry.map_err(io_error(format!("Unable to read {}",dir.display())))?.path().join("deps");
+            if !deps_dir.is_dir() {
+                continue;
+            }
+
+            for entry in read_dir(&deps_dir).map_err(io_error(format!("Unable to read {}",deps_dir.display())))? {
+                let path=entry.map_err(io_error(format!("Unable to read {}",deps_dir.display())))?.path();
+                let name=path.file_name().map(|n|n.to_string_lossy().to_string()).unwrap_or_default();
+                if name.starts_with("libSDL") && name.ends_with(".so") {
+                    libs.push(path);
+                }
+            }
+        }
+    }
+
+    Ok(libs)
+}
+
+pub fn clean(package: &ResolvedPackage, options: &CleanOptions)->SdlApkResult<()> {
+    let project_dir=package.android_project_dir();
+
+    if options.project {
+        remove_paths(vec![project_dir.clone()])?;
+    }
+
+    if options.native_libs {
+        let mut paths=find_copied_libs(&package.target_dir)?;
+        paths.push(project_dir.join("app/src/main/jniLibs"));
+        remove_paths(paths)?;
+    }
+
+    if options.sdl {
+        let mut paths=vec![
+            package.target_dir.join("sdl-build"),
+            package.target_dir.join("sdl-static")
+        ];
+
+        // SDL is optional with static SDL, which doesn't use ndk-build
+        if let Ok(sdl_dir)=get_env_var("SDL") {
+            let sdl_dir=PathBuf::from(sdl_dir);
+            paths.push(sdl_dir.join("libs"));
+            paths.push(sdl_dir.join("obj"));
+        }
+
+        remove_paths(paths)?;
+    }
+
+    if options.cargo {
+        remove_paths(SUPPORTED_TARGETS.iter().map(|t|package.target_dir.join(t)).collect())?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use std::fmt;
+use std::io;
+use std::process::ExitStatus;
+
+#[derive(Debug)]
+pub enum SdlApkError {
+    Usage(String),
+    MissingEnvVar(String),
+    ToolNotFound(String),
+    ToolFailed {
+        command: String,
+        status: ExitStatus
+    },
+    BadMetadata(String),
+    UnsupportedTarget(String),
+    Cargo(anyhow::Error),
+    Workspace(anyhow::Error),
+    Io(String, io::Error),
+    Launch(String),
+    Template(String),
+    SdlVersionMismatch(String)
+}
+
+impl SdlApkError {
+    // The process exit code for this kind of error, so that scripts can
+    // tell failures apart without parsing the message.
+    pub fn exit_code(&self)->i32 {
+        match self {
+            SdlApkError::Usage(_)=>2,
+            SdlApkError::MissingEnvVar(_)=>3,
+            SdlApkError::ToolNotFound(_)=>4,
+            SdlApkError::ToolFailed{..}=>5,
+            SdlApkError::BadMetadata(_)=>6,
+            SdlApkError::Cargo(_)=>7,
+            SdlApkError::Io(_,_)=>8,
+            SdlApkError::Launch(_)=>9,
+            SdlApkError::Template(_)=>10,
+            SdlApkError::SdlVersionMismatch(_)=>11,
+            SdlApkError::UnsupportedTarget(_)=>12,
+            SdlApkError::Workspace(_)=>13
+        }
+    }
+}
+
+impl fmt::Display for SdlApkError {
+    fn fmt(&self, f: &mut fmt::Formatter)->fmt::Result {
+        match self {
+            SdlApkError::Usage(s)=>write!(f,"{}",s),
+            SdlApkError::MissingEnvVar(k)=>write!(f,"Need env var: {}",k),
+            SdlApkError::ToolNotFound(t)=>write!(f,"Tool not found: {}",t),
+            SdlApkError::ToolFailed{command,status}=>write!(f,"Command failed ({}): {}",status,command),
+            SdlApkError::BadMetadata(s)=>write!(f,"Bad metadata: {}",s),
+            SdlApkError::UnsupportedTarget(t)=>write!(f,"Unsupported target: {}",t),
+            SdlApkError::Cargo(e)=>write!(f,"Cargo: {:#}",e),
+            SdlApkError::Workspace(e)=>write!(f,"Cargo workspace: {:#}",e),
+            SdlApkError::Io(context,e)=>write!(f,"{}: {}",context,e),
+            SdlApkError::Launch(s)=>write!(f,"Unable to launch app: {}",s),
+            SdlApkError::Template(s)=>write!(f,"Android project template: {}",s),
+            SdlApkError::SdlVersionMismatch(s)=>write!(f,"SDL version mismatch: {}",s)
+        }
+    }
+}
+
+impl std::error::Error for SdlApkError {
+    fn source(&self)->Option<&(dyn std::error::Error+'static)> {
+        match self {
+            SdlApkError::Io(_,e)=>Some(e),
+            _=>None
+        }
+    }
+}
+
+pub type SdlApkResult<T> = Result<T, SdlApkError>;
+
+// For use with map_err, e.g. .map_err(io_error("Unable to write file")).
+pub fn io_error<S: Into<String>>(context: S)->impl FnOnce(io::Error)->SdlApkError {
+    move |e|SdlApkError::Io(context.into(),e)
+}
+
+pub fn fs_error<S: Into<String>>(context: S)->impl FnOnce(fs_extra::error::Error)->SdlApkError {
+    move |e|SdlApkError::Io(context.into(),io::Error::other(e))
+}
+
+pub fn walkdir_error<S: Into<String>>(context: S)->impl FnOnce(walkdir::Error)->SdlApkError {
+    move |e|SdlApkError::Io(context.into(),e.into())
+}
@@ -0,0 +1,298 @@
+use std::path::Path;
+use crate::util::*;
+use crate::error::*;
+
+// Point every "APP_PLATFORM=android-NN" argument at the given API level.
+pub fn set_app_platform(content: &str, api_level: i64)->String {
+    let pattern="APP_PLATFORM=android-";
+    let mut result=String::new();
+    let mut rest=content;
+    while let Some(index)=rest.find(pattern) {
+        let after=&rest[index+pattern.len()..];
+        let digits=after.chars().take_while(|c|c.is_ascii_digit()).count();
+        result.push_str(&rest[..index]);
+        result.push_str(&format!("{}{}",pattern,api_level));
+        rest=&after[digits..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+// Make the asset merge tasks depend on the native build only if it exists,
+// since there is none when building with EXCLUDE_NATIVE_LIBS.
+pub fn set_optional_native_build(content: &str)->String {
+    content.replace(
+        ".dependsOn(\"externalNativeBuild${variant.name.capitalize()}\")",
+        ".dependsOn(tasks.matching { it.name == \"externalNativeBuild${variant.name.capitalize()}\" })"
+    )
+}
+
+// Quote a string for use in a gradle file. Single quotes, since groovy
+// interpolates double quoted strings.
+pub fn gradle_string(s: &str)->String {
+    format!("'{}'",s.replace('\\',"\\\\").replace('\'',"\\'"))
+}
+
+// Replace the value of every line in a gradle file that sets the given
+// property, e.g. "minSdkVersion 16", keeping the indentation.
+pub fn set_gradle_property(content: &str, key: &str, value: &str)->String {
+    let mut lines:Vec<String>=vec![];
+    for line in content.lines() {
+        let trimmed=line.trim_start();
+        if trimmed.split_whitespace().next()==Some(key) {
+            let indent=&line[..line.len()-trimmed.len()];
+            lines.push(format!("{}{} {}",indent,key,value));
+        }
+
+        else {
+            lines.push(line.to_string());
+        }
+    }
+
+    let mut content=lines.join("\n");
+    content.push('\n');
+    content
+}
+
+// The [package.metadata.android.gradle] section.
+#[derive(Clone,Debug,Default)]
+pub struct GradleMetadata {
+    pub dependencies: Vec<String>,
+    pub repositories: Vec<String>,
+    pub android_block: Option<String>
+}
+
+pub fn get_gradle_metadata(manifest_path: &Path)->SdlApkResult<GradleMetadata> {
+    let path=|key|vec!["package","metadata","android","gradle",key];
+
+    Ok(GradleMetadata {
+        dependencies: get_toml_string_array(manifest_path,path("dependencies"))?.unwrap_or_default(),
+        repositories: get_toml_string_array(manifest_path,path("repositories"))?.unwrap_or_default(),
+        android_block: get_toml_string(manifest_path,path("android_block"))?
+    })
+}
+
+// A top level block in a gradle file, e.g. "android { ... }". The close
+// index is the position of the closing brace.
+struct GradleBlock {
+    name: String,
+    close: usize
+}
+
+// Find the top level blocks of a gradle file by matching braces, skipping
+// strings and comments.
+fn find_gradle_blocks(content: &str)->Vec<GradleBlock> {
+    let bytes=content.as_bytes();
+    let mut blocks=vec![];
+    let mut depth=0;
+    let mut name=String::new();
+    let mut i=0;
+    while i<bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i+1)==Some(&b'/')=>{
+                while i<bytes.len() && bytes[i]!=b'\n' {
+                    i+=1;
+                }
+            },
+            b'/' if bytes.get(i+1)==Some(&b'*')=>{
+                i+=2;
+                while i<bytes.len() && !(bytes[i]==b'*' && bytes.get(i+1)==Some(&b'/')) {
+                    i+=1;
+                }
+                i+=1;
+            },
+            quote @ (b'\'' | b'"')=>{
+                i+=1;
+                while i<bytes.len() && bytes[i]!=quote {
+                    if bytes[i]==b'\\' {
+                        i+=1;
+                    }
+                    i+=1;
+                }
+            },
+            b'{'=>{
+                if depth==0 {
+                    let before=content[..i].trim_end();
+                    let start=before.rfind(|c:char|!(c.is_alphanumeric() || c=='_')).map(|p|p+1).unwrap_or(0);
+                    name=before[start..].to_string();
+                }
+                depth+=1;
+            },
+            b'}'=>{
+                depth-=1;
+                if depth==0 {
+                    blocks.push(GradleBlock {name: name.clone(), close: i});
+                }
+            },
+            _=>{}
+        }
+        i+=1;
+    }
+
+    blocks
+}
+
+fn indent(lines: &[String], prefix: &str)->String {
+    lines.iter()
+        .flat_map(|l|l.lines().map(|l|l.to_string()).collect::<Vec<String>>())
+        .map(|l|if l.trim().is_empty() {String::new()} else {format!("{}{}\n",prefix,l.trim_end())})
+        .collect()
+}
+
+// Add lines at the end of a top level block. If there is no such block,
+// it is appended to the file.
+fn append_to_gradle_block(content: &str, block_name: &str, lines: &[String])->String {
+    if lines.is_empty() {
+        return content.to_string();
+    }
+
+    let block=find_gradle_blocks(content).into_iter().rev().find(|b|b.name==block_name);
+    match block {
+        Some(block)=>{
+            // Insert on a line of its own before the closing brace.
+            let line_start=content[..block.close].rfind('\n').map(|p|p+1).unwrap_or(0);
+            let insert=indent(lines,"    ");
+            if content[line_start..block.close].trim().is_empty() {
+                format!("{}{}{}",&content[..line_start],insert,&content[line_start..])
+            } else {
+                format!("{}\n{}{}",&content[..block.close],insert,&content[block.close..])
+            }
+        },
+        None=>{
+            let mut content=content.to_string();
+            if !content.ends_with('\n') {
+                content.push('\n');
+            }
+            format!("{}\n{} {{\n{}}}\n",content,block_name,indent(lines,"    "))
+        }
+    }
+}
+
+// A dependency is either a plain coordinate, "group:name:version", which
+// becomes an implementation dependency, or a full gradle line.
+fn dependency_line(dependency: &str)->String {
+    if dependency.contains(char::is_whitespace) || dependency.contains('(') {
+        dependency.to_string()
+    } else {
+        format!("implementation {}",gradle_string(dependency))
+    }
+}
+
+// Merge the gradle metadata into app/build.gradle: dependencies and
+// repositories at the end of their top level blocks, and the android
+// block snippet at the end of the android block.
+pub fn customize_build_gradle(content: &str, metadata: &GradleMetadata)->SdlApkResult<String> {
+    let mut content=content.to_string();
+    if let Some(android_block)=&metadata.android_block {
+        if !find_gradle_blocks(&content).iter().any(|b|b.name=="android") {
+            return Err(SdlApkError::Template("app/build.gradle has no android block".to_string()));
+        }
+        content=append_to_gradle_block(&content,"android",std::slice::from_ref(android_block));
+    }
+
+    content=append_to_gradle_block(&content,"repositories",&metadata.repositories);
+    let dependencies:Vec<String>=metadata.dependencies.iter().map(|d|dependency_line(d)).collect();
+    content=append_to_gradle_block(&content,"dependencies",&dependencies);
+
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUILD_GRADLE: &str=include_str!("../testdata/sdl-2.26/build.gradle");
+
+    #[test]
+    fn app_platform_is_replaced() {
+        let content=set_app_platform(BUILD_GRADLE,26);
+        assert!(content.contains("arguments \"APP_PLATFORM=android-26\""));
+        assert!(!content.contains("android-16"));
+        assert_eq!(set_app_platform("APP_PLATFORM=android-",21),"APP_PLATFORM=android-21");
+    }
+
+    #[test]
+    fn property_keeps_indentation_and_comments() {
+        let content=set_gradle_property(BUILD_GRADLE,"minSdkVersion","26");
+        assert!(content.contains("\n        minSdkVersion 26\n"));
+        assert!(!content.contains("minSdkVersion 16"));
+
+        let content=set_gradle_property(BUILD_GRADLE,"abiFilters","'arm64-v8a'");
+        assert!(content.contains("\n                abiFilters 'arm64-v8a'\n"));
+        assert!(content.contains("//     // abiFilters 'armeabi-v7a', 'arm64-v8a', 'x86', 'x86_64'"));
+        assert!(content.contains("//     abiFilters 'arm64-v8a'"));
+    }
+
+    #[test]
+    fn string_quoting() {
+        assert_eq!(gradle_string("1.0"),"'1.0'");
+        assert_eq!(gradle_string("it's"),"'it\\'s'");
+        assert_eq!(gradle_string("a\\b"),"'a\\\\b'");
+    }
+
+    #[test]
+    fn top_level_blocks() {
+        let blocks=find_gradle_blocks(BUILD_GRADLE);
+        let names:Vec<&str>=blocks.iter().map(|b|&*b.name).collect();
+        assert_eq!(names,vec!["","else","android","dependencies"]);
+        for block in &blocks {
+            assert_eq!(&BUILD_GRADLE[block.close..block.close+1],"}");
+        }
+        assert!(BUILD_GRADLE[blocks[3].close+1..].trim().is_empty());
+    }
+
+    #[test]
+    fn blocks_skip_strings_and_comments() {
+        let content="a { s = '}' }\n// b {\n/* c { */\nd { t = \"\\\"}\" }\n";
+        let names:Vec<String>=find_gradle_blocks(content).into_iter().map(|b|b.name).collect();
+        assert_eq!(names,vec!["a","d"]);
+    }
+
+    #[test]
+    fn customize_appends_to_blocks() {
+        let metadata=GradleMetadata {
+            dependencies: vec![
+                "androidx.core:core:1.9.0".to_string(),
+                "implementation files('libs/x.jar')".to_string()
+            ],
+            repositories: vec!["google()".to_string()],
+            android_block: Some("packagingOptions {\n    jniLibs.useLegacyPackaging true\n}".to_string())
+        };
+
+        let content=customize_build_gradle(BUILD_GRADLE,&metadata).unwrap();
+        let blocks=find_gradle_blocks(&content);
+        let android=blocks.iter().find(|b|b.name=="android").unwrap();
+        let dependencies=blocks.iter().find(|b|b.name=="dependencies").unwrap();
+
+        let android_end=&content[..android.close];
+        assert!(android_end.ends_with("    packagingOptions {\n        jniLibs.useLegacyPackaging true\n    }\n"));
+
+        let dependencies_end=&content[..dependencies.close];
+        assert!(dependencies_end.ends_with(
+            "    implementation 'androidx.core:core:1.9.0'\n    implementation files('libs/x.jar')\n"
+        ));
+        assert!(content.ends_with("\nrepositories {\n    google()\n}\n"));
+    }
+
+    #[test]
+    fn customize_without_metadata_keeps_content() {
+        assert_eq!(customize_build_gradle(BUILD_GRADLE,&GradleMetadata::default()).unwrap(),BUILD_GRADLE);
+    }
+
+    #[test]
+    fn customize_needs_android_block() {
+        let metadata=GradleMetadata {
+            android_block: Some("x".to_string()),
+            ..GradleMetadata::default()
+        };
+        assert!(matches!(customize_build_gradle("dependencies {\n}\n",&metadata),Err(SdlApkError::Template(_))));
+    }
+
+    #[test]
+    fn optional_native_build() {
+        let content=set_optional_native_build(BUILD_GRADLE);
+        assert!(content.contains(".dependsOn(tasks.matching { it.name == \"externalNativeBuild${variant.name.capitalize()}\" })"));
+        assert!(!content.contains(".dependsOn(\"externalNativeBuild"));
+    }
+}
@@ -0,0 +1,161 @@
+use std::path::{Path, PathBuf};
+use std::io::Cursor;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageError, ImageOutputFormat};
+use crate::util::*;
+use crate::error::*;
+use crate::xml::*;
+use crate::android_project::*;
+use crate::project_files::*;
+
+// Launcher icon sizes in pixels for each density, for legacy icons (48dp)
+// and adaptive icon layers (108dp).
+const DENSITIES: [(&str, u32, u32); 5] = [
+    ("mdpi", 48, 108),
+    ("hdpi", 72, 162),
+    ("xhdpi", 96, 216),
+    ("xxhdpi", 144, 324),
+    ("xxxhdpi", 192, 432)
+];
+
+#[derive(Clone,Debug)]
+pub enum IconBackground {
+    Image(PathBuf),
+    Color(String)
+}
+
+// The icon keys in [package.metadata.android]. An example that sets icon or
+// adaptive_icon replaces both, since the adaptive icon is preferred where
+// supported. Paths are relative to the package manifest.
+#[derive(Clone,Debug,Default)]
+pub struct IconMetadata {
+    pub icon: Option<PathBuf>,
+    pub adaptive_foreground: Option<PathBuf>,
+    pub adaptive_background: Option<IconBackground>
+}
+
+pub fn get_icon_metadata(manifest_path: &Path, example: Option<&str>)->SdlApkResult<IconMetadata> {
+    let manifest_dir=manifest_path.parent().unwrap_or(Path::new("."));
+    let (table,_)=get_android_metadata_table(manifest_path,example,&["icon","adaptive_icon"])?;
+    let get=|keys:&[&str]|get_toml_string(manifest_path,[&table[..],keys].concat());
+
+    let icon=get(&["icon"])?;
+    let foreground=get(&["adaptive_icon","foreground"])?;
+    let background=get(&["adaptive_icon","background"])?;
+
+    if foreground.is_some()!=background.is_some() {
+        return Err(SdlApkError::BadMetadata(
+            "adaptive_icon needs both foreground and background".to_string()
+        ));
+    }
+
+    Ok(IconMetadata {
+        icon: icon.map(|p|manifest_dir.join(p)),
+        adaptive_foreground: foreground.map(|p|manifest_dir.join(p)),
+        adaptive_background: background.map(|b|{
+            if b.starts_with('#') {
+                IconBackground::Color(b)
+            } else {
+                IconBackground::Image(manifest_dir.join(b))
+            }
+        })
+    })
+}
+
+fn load_icon_image(path: &Path, min_size: u32)->SdlApkResult<DynamicImage> {
+    let image=image::open(path).map_err(|e|match e {
+        ImageError::IoError(e)=>SdlApkError::Io(format!("Unable to read {}",path.display()),e),
+        e=>SdlApkError::BadMetadata(format!("Unable to load {}: {}",path.display(),e))
+    })?;
+
+    if image.width()!=image.height() {
+        return Err(SdlApkError::BadMetadata(format!(
+            "Icon {} is {}x{}, it needs to be square",
+            path.display(),image.width(),image.height()
+        )));
+    }
+
+    if image.width()<min_size {
+        return Err(SdlApkError::BadMetadata(format!(
+            "Icon {} is {}x{}, it needs to be at least {}x{}",
+            path.display(),image.width(),image.height(),min_size,min_size
+        )));
+    }
+
+    Ok(image)
+}
+
+fn add_mipmaps(image: &DynamicImage, files: &mut ProjectFiles, res_dir: &str, name: &str, adaptive: bool)->SdlApkResult<()> {
+    for (density,legacy_size,adaptive_size) in DENSITIES {
+        let size=if adaptive {adaptive_size} else {legacy_size};
+        let file=format!("{}/mipmap-{}/{}.png",res_dir,density,name);
+        let mut content=vec![];
+        image.resize_exact(size,size,FilterType::Lanczos3)
+            .write_to(&mut Cursor::new(&mut content),ImageOutputFormat::Png)
+            .map_err(|e|SdlApkError::Io(
+                format!("Unable to encode {}",file),
+                std::io::Error::other(e)
+            ))?;
+
+        files.insert(file,ProjectFile {content, executable: false});
+    }
+
+    Ok(())
+}
+
+fn add_xml(files: &mut ProjectFiles, file: String, element: &XmlElement)->SdlApkResult<()> {
+    files.insert(file,ProjectFile {
+        content: element.to_xml_string()?.into_bytes(),
+        executable: false
+    });
+
+    Ok(())
+}
+
+// Add launcher icons to the files of the project, below the res dir. If
+// no icon is configured, the icons from the SDL template are kept.
+pub fn add_icons(files: &mut ProjectFiles, res_dir: &str, metadata: &IconMetadata)->SdlApkResult<()> {
+    if let Some(icon)=&metadata.icon {
+        let image=load_icon_image(icon,DENSITIES[4].1)?;
+        add_mipmaps(&image,files,res_dir,"ic_launcher",false)?;
+    }
+
+    if let (Some(foreground),Some(background))=(&metadata.adaptive_foreground,&metadata.adaptive_background) {
+        let image=load_icon_image(foreground,DENSITIES[4].2)?;
+        add_mipmaps(&image,files,res_dir,"ic_launcher_foreground",true)?;
+
+        let background_drawable=match background {
+            IconBackground::Image(path)=>{
+                let image=load_icon_image(path,DENSITIES[4].2)?;
+                add_mipmaps(&image,files,res_dir,"ic_launcher_background",true)?;
+                "@mipmap/ic_launcher_background"
+            },
+            IconBackground::Color(color)=>{
+                let mut resources=XmlElement::new("resources");
+                resources.children.push(XmlNode::Element(XmlElement {
+                    children: vec![XmlNode::Text(color.clone())],
+                    ..XmlElement::new("color").with_attribute("name","ic_launcher_background")
+                }));
+
+                add_xml(files,format!("{}/values/ic_launcher_background.xml",res_dir),&resources)?;
+                "@color/ic_launcher_background"
+            }
+        };
+
+        let mut adaptive_icon=XmlElement::new("adaptive-icon");
+        adaptive_icon.namespaces.push((
+            "android".to_string(),
+            "http://schemas.android.com/apk/res/android".to_string()
+        ));
+        adaptive_icon.children.push(XmlNode::Element(
+            XmlElement::new("background").with_attribute("android:drawable",background_drawable)
+        ));
+        adaptive_icon.children.push(XmlNode::Element(
+            XmlElement::new("foreground").with_attribute("android:drawable","@mipmap/ic_launcher_foreground")
+        ));
+
+        add_xml(files,format!("{}/mipmap-anydpi-v26/ic_launcher.xml",res_dir),&adaptive_icon)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,266 @@
+use std::path::{Path, PathBuf};
+use std::fs::{read, read_to_string};
+use walkdir::WalkDir;
+use crate::util::*;
+use crate::error::*;
+use crate::project_files::*;
+use crate::android_project::*;
+
+// The java keys in [package.metadata.android]. Paths are relative to the
+// package manifest. The main_activity can be overridden per example, and
+// is written for the app id in main_activity_app_id.
+#[derive(Clone,Debug,Default)]
+pub struct JavaMetadata {
+    pub java_sources: Vec<PathBuf>,
+    pub main_activity: Option<PathBuf>,
+    pub main_activity_app_id: String
+}
+
+pub fn get_java_metadata(manifest_path: &Path, example: Option<&str>)->SdlApkResult<JavaMetadata> {
+    let manifest_dir=manifest_path.parent().unwrap_or(Path::new("."));
+    let (main_activity_path,from_example)=get_android_metadata_key(manifest_path,example,"main_activity")?;
+    let main_activity_example=if from_example {example} else {None};
+
+    Ok(JavaMetadata {
+        java_sources: get_toml_string_array(manifest_path,vec!["package","metadata","android","java_sources"])?
+            .unwrap_or_default()
+            .iter()
+            .map(|d|manifest_dir.join(d))
+            .collect(),
+        main_activity: get_toml_string(manifest_path,main_activity_path)?
+            .map(|p|manifest_dir.join(p)),
+        main_activity_app_id: get_android_app_id(manifest_path,main_activity_example)?
+    })
+}
+
+// Find the package declaration of a java source file, or None if it is in
+// the default package.
+fn get_java_package(source: &str)->Option<String> {
+    let mut in_comment=false;
+    for line in source.lines() {
+        let line=line.trim();
+        if in_comment {
+            in_comment = !line.contains("*/");
+            continue;
+        }
+
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+
+        if line.starts_with("/*") {
+            in_comment = !line.contains("*/");
+            continue;
+        }
+
+        return line.strip_prefix("package ")
+            .and_then(|rest|rest.split(';').next())
+            .map(|p|p.trim().to_string());
+    }
+
+    None
+}
+
+fn read_java_package(path: &Path)->SdlApkResult<Option<String>> {
+    let source=read_to_string(path).map_err(io_error(format!("Unable to read {}",path.display())))?;
+    Ok(get_java_package(&source))
+}
+
+fn package_to_dir(package: &str)->PathBuf {
+    package.split('.').collect()
+}
+
+// Find the SDLActivity class of the SDL java sources in the project.
+fn find_sdl_activity(files: &ProjectFiles, java_dir: &str)->SdlApkResult<String> {
+    for name in files.dir_files(java_dir) {
+        if name=="SDLActivity.java" || name.ends_with("/SDLActivity.java") {
+            let package=files.get(&format!("{}/{}",java_dir,name))
+                .and_then(|f|std::str::from_utf8(&f.content).ok())
+                .and_then(get_java_package);
+            if let Some(package)=package {
+                return Ok(format!("{}.SDLActivity",package));
+            }
+        }
+    }
+
+    Err(SdlApkError::Template("SDLActivity.java not found".to_string()))
+}
+
+// The generated MainActivity. If libraries are given, they are loaded
+// instead of the ones SDLActivity loads by default.
+fn generate_main_activity(app_id: &str, sdl_activity: &str, libraries: Option<&[String]>)->String {
+    let libraries=match libraries {
+        Some(libraries)=>libraries,
+        None=>{
+            let main_class = "
+		package $APP;
+
+		import $SDL_ACTIVITY;
+
+		public class MainActivity extends SDLActivity {
+		}
+	";
+            return str::replace(main_class, "$APP", app_id).replace("$SDL_ACTIVITY", sdl_activity);
+        }
+    };
+
+    let names=libraries.iter().map(|n|format!("\"{}\"",n)).collect::<Vec<String>>().join(", ");
+
+    let main_class = "
+		package $APP;
+
+		import $SDL_ACTIVITY;
+
+		public class MainActivity extends SDLActivity {
+			@Override
+			protected String[] getLibraries() {
+				return new String[] { $LIBRARIES };
+			}
+		}
+	";
+    str::replace(main_class, "$APP", app_id)
+        .replace("$SDL_ACTIVITY", sdl_activity)
+        .replace("$LIBRARIES", &names)
+}
+
+// Move a java source file to another package.
+fn set_java_package(source: &str, from: &str, to: &str)->Option<String> {
+    let declaration=format!("package {};",from);
+    if !source.contains(&declaration) {
+        return None;
+    }
+
+    Some(source.replacen(&declaration,&format!("package {};",to),1))
+}
+
+fn add_main_activity(
+        files: &mut ProjectFiles,
+        java_dir: &str,
+        app_id: &str,
+        metadata: &JavaMetadata,
+        libraries: Option<&[String]>
+    )->SdlApkResult<()> {
+    let main_class=match &metadata.main_activity {
+        Some(path)=>{
+            let expected=&metadata.main_activity_app_id;
+            if read_java_package(path)?.as_ref()!=Some(expected) {
+                return Err(SdlApkError::BadMetadata(format!(
+                    "main_activity {} should be in package {}",
+                    path.display(),expected
+                )));
+            }
+
+            // An example using the main_activity of the package gets it
+            // in the package of its own app id.
+            let source=read_to_string(path).map_err(io_error(format!("Unable to read {}",path.display())))?;
+            set_java_package(&source,expected,app_id).ok_or_else(||SdlApkError::BadMetadata(format!(
+                "Unable to find the package declaration in main_activity {}",
+                path.display()
+            )))?
+        },
+        None=>generate_main_activity(app_id,&find_sdl_activity(files,java_dir)?,libraries)
+    };
+
+    files.insert(
+        format!("{}/{}",java_dir,path_to_name(&package_to_dir(app_id).join("MainActivity.java"))),
+        ProjectFile {content: main_class.into_bytes(), executable: false}
+    );
+
+    Ok(())
+}
+
+// Add the java source dirs to the project files, and MainActivity.java,
+// either the generated one or the one from main_activity. Each file must
+// declare the package matching its directory. The generated MainActivity
+// loads the given libraries, which end with the main library.
+pub fn install_java_sources(
+        files: &mut ProjectFiles,
+        java_dir: &str,
+        app_id: &str,
+        metadata: &JavaMetadata,
+        libraries: Option<&[String]>
+    )->SdlApkResult<()> {
+    let main_activity_path=package_to_dir(app_id).join("MainActivity.java");
+
+    for dir in &metadata.java_sources {
+        if !dir.is_dir() {
+            return Err(SdlApkError::BadMetadata(format!("Java source dir not found: {}",dir.display())));
+        }
+
+        for entry in WalkDir::new(dir).follow_links(true) {
+            let entry=entry.map_err(walkdir_error(format!("Unable to read {}",dir.display())))?;
+
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let rel=entry.path().strip_prefix(dir).unwrap_or(entry.path());
+            if rel==main_activity_path {
+                return Err(SdlApkError::BadMetadata(format!(
+                    "{} would replace the generated MainActivity, use main_activity instead",
+                    entry.path().display()
+                )));
+            }
+
+            if rel.extension().and_then(|e|e.to_str())==Some("java") {
+                let expected=rel.parent()
+                    .map(|p|p.iter().map(|c|c.to_string_lossy()).collect::<Vec<_>>().join("."))
+                    .unwrap_or_default();
+                let declared=read_java_package(entry.path())?.unwrap_or_default();
+                if declared!=expected {
+                    return Err(SdlApkError::BadMetadata(format!(
+                        "{} declares package \"{}\", but its directory means \"{}\"",
+                        entry.path().display(),declared,expected
+                    )));
+                }
+            }
+
+            let content=read(entry.path()).map_err(io_error(format!("Unable to read {}",entry.path().display())))?;
+            files.insert(format!("{}/{}",java_dir,path_to_name(rel)),ProjectFile {content, executable: false});
+        }
+    }
+
+    add_main_activity(files,java_dir,app_id,metadata,libraries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_declaration() {
+        assert_eq!(get_java_package("package com.example;\nclass A {}"),Some("com.example".to_string()));
+        assert_eq!(get_java_package("  package  com.example ;"),Some("com.example".to_string()));
+        assert_eq!(get_java_package("import x.Y;\npackage com.example;"),None);
+        assert_eq!(get_java_package("class A {}"),None);
+        assert_eq!(get_java_package(""),None);
+    }
+
+    #[test]
+    fn package_after_comments() {
+        let source="// Copyright\n\n/*\n * package wrong;\n */\n/* one line */\npackage org.libsdl.app;\n";
+        assert_eq!(get_java_package(source),Some("org.libsdl.app".to_string()));
+        assert_eq!(get_java_package("/** doc */ package a.b;"),None);
+    }
+
+    #[test]
+    fn moving_package() {
+        assert_eq!(
+            set_java_package("package com.k;\nimport com.k.x.Y;\n","com.k","com.k.e").unwrap(),
+            "package com.k.e;\nimport com.k.x.Y;\n"
+        );
+        assert_eq!(set_java_package("package com.other;\n","com.k","com.k.e"),None);
+    }
+
+    #[test]
+    fn generated_main_activity() {
+        let plain=generate_main_activity("com.example","org.libsdl.app.SDLActivity",None);
+        assert_eq!(get_java_package(&plain),Some("com.example".to_string()));
+        assert!(plain.contains("import org.libsdl.app.SDLActivity;"));
+        assert!(!plain.contains("getLibraries"));
+
+        let libraries=["SDL2".to_string(),"SDL2_image".to_string(),"main".to_string()];
+        let custom=generate_main_activity("com.example","org.libsdl.app.SDLActivity",Some(&libraries));
+        assert!(custom.contains("return new String[] { \"SDL2\", \"SDL2_image\", \"main\" };"));
+    }
+}
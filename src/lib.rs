@@ -0,0 +1,71 @@
+//! Build Android packages that use SDL.
+//!
+//! This is the library behind the `cargo sdl-apk` command. It can be used
+//! to build APKs from other tools:
+//!
+//! ```no_run
+//! use cargo_sdl_apk::{ApkBuilder, BuildProfile};
+//!
+//! let output=ApkBuilder::new("Cargo.toml")
+//!     .targets(["aarch64-linux-android"])
+//!     .profile(BuildProfile::Release)
+//!     .build()
+//!     .unwrap();
+//!
+//! println!("Built {}",output.apk_path.display());
+//! ```
+
+mod build_bin_as_lib;
+mod util;
+mod error;
+mod android_project;
+mod apk_builder;
+mod workspace;
+mod xml;
+mod android_manifest;
+mod icon;
+mod assets;
+mod java_sources;
+mod gradle;
+mod template;
+mod project_files;
+mod sdl;
+mod clean;
+
+pub use build_bin_as_lib::BuildTarget;
+pub use error::{SdlApkError, SdlApkResult};
+pub use clean::CleanOptions;
+pub use apk_builder::{ApkBuilder, ApkOutput, AbiArtifact, Signing, run_apk};
+
+/// The gradle variant to build. Unless a cargo profile is given
+/// explicitly, this also selects the dev or release cargo profile.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum BuildProfile {
+    Debug,
+    Release
+}
+
+impl BuildProfile {
+    pub fn default_cargo_profile(&self)->&'static str {
+        match self {
+            BuildProfile::Debug=>"dev",
+            BuildProfile::Release=>"release"
+        }
+    }
+}
+
+impl std::fmt::Display for BuildProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter)->std::fmt::Result {
+        match self {
+            BuildProfile::Debug=>write!(f,"debug"),
+            BuildProfile::Release=>write!(f,"release")
+        }
+    }
+}
+
+pub const SUPPORTED_TARGETS: [&str; 4] = [
+    "aarch64-linux-android",
+    "armv7-linux-androideabi",
+    "i686-linux-android",
+    "x86_64-linux-android"
+];
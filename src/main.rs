@@ -1,30 +1,5 @@
-use std::fs::canonicalize;
-use std::process::Command;
-use std::path::Path;
-
-mod build_bin_as_lib;
-use build_bin_as_lib::*;
-
-mod util;
-use util::*;
-
-mod android_project;
-use android_project::*;
-
-#[derive(Clone,Copy)]
-pub enum BuildProfile {
-    Debug,
-    Release
-}
-
-impl BuildProfile {
-    pub fn to_string(&self)->String {
-        match self {
-            BuildProfile::Debug=>"debug".to_string(),
-            BuildProfile::Release=>"release".to_string()
-        }
-    }
-}
+use std::path::PathBuf;
+use cargo_sdl_apk::*;
 
 const HELP: &str = "
 cargo-sdl-apk -- Build APKs with Rust and SDL.
@@ -35,24 +10,71 @@ USAGE:
 COMMANDS:
   build                 Build APK from bin target.
   run                   Build APK and run using adb.
+  clean                 Remove generated files. Without flags, everything
+                        except cargo's own output is removed.
 
 OPTIONS:
   --manifest-path PATH  Path to Cargo.toml.
+  -p, --package SPEC    Workspace member to package.
+  --target-dir DIRECTORY
+                        Directory for all generated artifacts.
+  --bin NAME            Build or run the named bin target. Only needed if the
+                        crate has several bins and no default_bin is set.
   --example EXAMPLE     Build or run crate example.
   --release             Build in release mode.
+  --profile PROFILE     Build with the given cargo profile.
+  --variant VARIANT     Gradle variant to build, debug or release. Defaults to
+                        release, unless building with the dev profile.
+  --no-sign             Don't sign release builds.
+  --rebuild-sdl         Build SDL even if the cached build is up to date.
+  --allow-sdl-mismatch  Build even if the SDL sources have a different major
+                        version than the sdl2 crate is made for.
+  --target TRIPLE       Build for the given target. Can be repeated. Defaults
+                        to build_targets in [package.metadata.android], or all
+                        supported targets.
+  -F, --features FEATURES
+                        Space or comma separated list of features to activate.
+                        Features in [package.metadata.android] are always
+                        activated.
+  --all-features        Activate all available features.
+  --no-default-features Do not activate the default feature.
   --ks FILE             Keystore file for signing. If omitted a self signed
                         key with pass 'android' will be generated.
   --ks-pass pass:PASS   Keystore pass. Only required if key file is specified.
+
+CLEAN OPTIONS:
+  --project             Remove the generated gradle project.
+  --libs                Remove the copied native libraries.
+  --sdl                 Remove the ndk-build output in the SDL source tree.
+  --cargo               Remove cargo's output for the Android targets.
+  --all                 Remove all of the above, except cargo's output.
 ";
 
 #[derive(Debug)]
 struct SdlApkArgs {
     manifest_path: String,
     command: String,
+    package: Option<String>,
+    target_dir: Option<String>,
+    bin: Option<String>,
     example: Option<String>,
     release: bool,
+    profile: Option<String>,
+    variant: Option<String>,
+    no_sign: bool,
+    rebuild_sdl: bool,
+    allow_sdl_mismatch: bool,
+    targets: Vec<String>,
+    features: Vec<String>,
+    all_features: bool,
+    no_default_features: bool,
     ks_file: Option<String>,
     ks_pass: Option<String>,
+    clean_project: bool,
+    clean_libs: bool,
+    clean_sdl: bool,
+    clean_cargo: bool,
+    clean_all: bool,
 }
 
 fn parse_args()->Result<SdlApkArgs, pico_args::Error> {
@@ -65,10 +87,27 @@ fn parse_args()->Result<SdlApkArgs, pico_args::Error> {
 
     let args=SdlApkArgs {
         manifest_path: pargs.value_from_str("--manifest-path").unwrap_or("Cargo.toml".to_string()),
+        package: pargs.opt_value_from_str(["-p","--package"])?,
+        target_dir: pargs.opt_value_from_str("--target-dir")?,
+        bin: pargs.opt_value_from_str("--bin")?,
         example: pargs.opt_value_from_str("--example")?,
         ks_file: pargs.opt_value_from_str("--ks-file")?,
         ks_pass: pargs.opt_value_from_str("--ks-pass")?,
         release: pargs.contains("--release"),
+        profile: pargs.opt_value_from_str("--profile")?,
+        variant: pargs.opt_value_from_str("--variant")?,
+        no_sign: pargs.contains("--no-sign"),
+        rebuild_sdl: pargs.contains("--rebuild-sdl"),
+        allow_sdl_mismatch: pargs.contains("--allow-sdl-mismatch"),
+        targets: pargs.values_from_str("--target")?,
+        features: pargs.values_from_str(["-F","--features"])?,
+        all_features: pargs.contains("--all-features"),
+        no_default_features: pargs.contains("--no-default-features"),
+        clean_project: pargs.contains("--project"),
+        clean_libs: pargs.contains("--libs"),
+        clean_sdl: pargs.contains("--sdl"),
+        clean_cargo: pargs.contains("--cargo"),
+        clean_all: pargs.contains("--all"),
         command: cmd
     };
 
@@ -82,95 +121,86 @@ fn parse_args()->Result<SdlApkArgs, pico_args::Error> {
     Ok(args)
 }
 
-fn build_android(
-        manifest_path: &Path, 
-        build_target:BuildTarget, 
-        build_profile:BuildProfile,
-        ks_file: Option<String>,
-        ks_pass: Option<String>
-    ) {
-    for k in &["ANDROID_HOME", "ANDROID_NDK_HOME", "SDL"] {
-        let _check_val = get_env_var(k);
+fn run(args: SdlApkArgs)->SdlApkResult<()> {
+    let build_target=match (&args.bin,&args.example) {
+        (None,None)=>BuildTarget::Bin,
+        (Some(s),None)=>BuildTarget::NamedBin(s.clone()),
+        (None,Some(s))=>BuildTarget::Example(s.clone()),
+        (Some(_),Some(_))=>return Err(SdlApkError::Usage("Can't use both --bin and --example".to_string()))
+    };
+
+    if args.release && !matches!(args.profile.as_deref(),None|Some("release")) {
+        return Err(SdlApkError::Usage("Can't use both --release and --profile".to_string()));
     }
 
-    let targets=vec![
-    	"aarch64-linux-android",
-    	"armv7-linux-androideabi",
-    	"i686-linux-android"
-    ];
+    let build_profile=match (args.variant.as_deref(),args.release,args.profile.as_deref()) {
+        (Some("debug"),_,_)=>BuildProfile::Debug,
+        (Some("release"),_,_)=>BuildProfile::Release,
+        (Some(v),_,_)=>return Err(SdlApkError::Usage(format!("Unknown variant: {}",v))),
+        (None,true,_)=>BuildProfile::Release,
+        (None,false,None|Some("dev")|Some("test"))=>BuildProfile::Debug,
+        (None,false,Some(_))=>BuildProfile::Release
+    };
 
-    build_sdl_for_android(&targets,build_profile);
-    let target_artifacts=build_bin_as_lib(&manifest_path,build_target,&targets,build_profile);
-    build_android_project(&manifest_path,&target_artifacts,build_profile,ks_file,ks_pass);
-}
+    let signing=match args.ks_file {
+        None=>Signing::SelfSigned,
+        Some(file)=>Signing::Keystore {
+            file: PathBuf::from(file),
+            pass: args.ks_pass.ok_or(SdlApkError::Usage("Need keystore password".to_string()))?
+        }
+    };
 
-fn run_android(
-        manifest_path: &Path, 
-        build_target:BuildTarget, 
-        build_profile:BuildProfile,
-        ks_file: Option<String>,
-        ks_pass: Option<String>
-    ) {
-    build_android(manifest_path,build_target,build_profile,ks_file,ks_pass);
-
-    let manifest_dir=manifest_path.parent().unwrap();
-
-    let appid = get_android_app_id(manifest_path);
-    let output_apk=match build_profile {
-        BuildProfile::Debug=>"debug/app-debug.apk",
-        BuildProfile::Release=>"release/app-release.apk",
+    let mut builder=ApkBuilder::new(&args.manifest_path)
+        .build_target(build_target)
+        .targets(args.targets)
+        .features(args.features)
+        .all_features(args.all_features)
+        .no_default_features(args.no_default_features)
+        .profile(build_profile)
+        .sign(!args.no_sign)
+        .rebuild_sdl(args.rebuild_sdl)
+        .allow_sdl_mismatch(args.allow_sdl_mismatch)
+        .signing(signing);
+
+    if let Some(package)=args.package {
+        builder=builder.package(package);
+    }
+    if let Some(target_dir)=args.target_dir {
+        builder=builder.target_dir(target_dir);
+    }
+    if let Some(cargo_profile)=args.profile {
+        builder=builder.cargo_profile(cargo_profile);
+    }
+    else if args.release {
+        builder=builder.cargo_profile("release");
+    }
+
+    let any_clean_flag=args.clean_project || args.clean_libs || args.clean_sdl || args.clean_cargo || args.clean_all;
+    if any_clean_flag && args.command!="clean" {
+        return Err(SdlApkError::Usage(format!(
+            "--project, --libs, --sdl, --cargo and --all can only be used with clean, not {}",
+            args.command
+        )));
+    }
+
+    let clean_options=if args.clean_all || !(args.clean_project || args.clean_libs || args.clean_sdl || args.clean_cargo) {
+        CleanOptions {cargo: args.clean_cargo, ..CleanOptions::all()}
+    } else {
+        CleanOptions {
+            project: args.clean_project,
+            native_libs: args.clean_libs,
+            sdl: args.clean_sdl,
+            cargo: args.clean_cargo
+        }
     };
 
-    let p = Path::new(&*get_env_var("ANDROID_HOME")).join("platform-tools/adb");
-    assert!(Command::new(p.clone())
-        .args([
-            "-d",
-            "install",
-            "-r",
-            &*manifest_dir
-                .join("target/android-project/app/build/outputs/apk/")
-                .join(output_apk)
-                .into_os_string().into_string().unwrap()
-        ])
-        .status()
-        .unwrap()
-        .success());
-
-    assert!(Command::new(p.clone())
-        .args(["shell", "am", "force-stop", &*appid])
-        .status()
-        .unwrap()
-        .success());
-
-    let mut activity = appid.clone();
-    activity.push_str("/.MainActivity");
-
-    assert!(Command::new(p.clone())
-        .args(["shell", "am", "start", "-W", "-n", &*activity])
-        .status()
-        .unwrap()
-        .success());
-
-    let pid_vec = Command::new(p.clone())
-        .arg("shell")
-        .arg("pidof")
-        .arg(&*appid)
-        .output()
-        .unwrap() //except("Can't get pid")
-        .stdout;
-
-    let pid = std::str::from_utf8(&pid_vec).unwrap().trim();
-    let pid: u32 = pid
-        .parse()
-        .unwrap();
-
-    println!("Launched with PID: {}", pid);
-
-    assert!(Command::new(p.clone())
-        .args(["logcat","-v","color","--pid",&*pid.to_string()])
-        .status()
-        .unwrap()
-        .success());
+    match &*args.command {
+        "sign"=>builder.sign_apk(),
+        "clean"=>builder.clean(&clean_options),
+        "build"=>builder.build().map(drop),
+        "run"=>run_apk(&builder.build()?),
+        _=>Err(SdlApkError::Usage(format!("Unknown command: {}.", args.command)))
+    }
 }
 
 fn main() {
@@ -179,47 +209,15 @@ fn main() {
         Err(e)=>{
             eprintln!("Error: {}.", e);
             println!("{}",HELP);
-            std::process::exit(1);
+            std::process::exit(SdlApkError::Usage(e.to_string()).exit_code());
         }
     };
 
-    let manifest_path=canonicalize(args.manifest_path).unwrap();
-
-    let build_target=match &args.example {
-        None=>BuildTarget::Bin,
-        Some(s)=>BuildTarget::Example(s.clone())
-    };
-
-    let build_profile=if args.release {
-        BuildProfile::Release
-    } else {
-        BuildProfile::Debug
-    };
-
-    match &*args.command {
-        "sign"=>sign_android(
-            &manifest_path,
-            args.ks_file,
-            args.ks_pass,
-        ),
-        "build"=>build_android(
-            &manifest_path,
-            build_target,
-            build_profile,
-            args.ks_file,
-            args.ks_pass
-        ),
-        "run"=>run_android(
-            &manifest_path,
-            build_target,
-            build_profile,
-            args.ks_file,
-            args.ks_pass
-        ),
-        _=>{
-            eprintln!("Unknown command: {}.", args.command);
+    if let Err(e)=run(args) {
+        eprintln!("Error: {}", e);
+        if matches!(e,SdlApkError::Usage(_)) {
             println!("{}",HELP);
-            std::process::exit(1);
         }
+        std::process::exit(e.exit_code());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,250 @@
+use std::path::Path;
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read, read_to_string, remove_dir, remove_file, write, Metadata};
+use sha2::{Digest, Sha256};
+use crate::error::*;
+
+// Where the generated files are recorded, relative to the project dir.
+const MANIFEST_FILE: &str=".sdl-apk-files";
+
+pub struct ProjectFile {
+    pub content: Vec<u8>,
+    pub executable: bool
+}
+
+// Files of the Android project generated from the template, keyed by
+// their path in the project.
+#[derive(Default)]
+pub struct ProjectFiles {
+    files: BTreeMap<String,ProjectFile>
+}
+
+struct FilesManifest {
+    sdl_version: String,
+    hashes: BTreeMap<String,String>
+}
+
+// The name of a file in the project for a relative path, with forward
+// slashes on all platforms.
+pub fn path_to_name(rel: &Path)->String {
+    rel.components()
+        .map(|c|c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// The executable bit only exists on unix, elsewhere files are never
+// executable.
+#[cfg(unix)]
+pub fn is_executable(metadata: &Metadata)->bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode()&0o111!=0
+}
+
+#[cfg(not(unix))]
+pub fn is_executable(_metadata: &Metadata)->bool {
+    false
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path, executable: bool)->std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode=if executable {0o755} else {0o644};
+    std::fs::set_permissions(path,std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path, _executable: bool)->std::io::Result<()> {
+    Ok(())
+}
+
+fn hash(content: &[u8])->String {
+    format!("{:x}",Sha256::digest(content))
+}
+
+fn read_manifest(path: &Path)->Option<FilesManifest> {
+    let content=read_to_string(path).ok()?;
+    let mut lines=content.lines();
+    let sdl_version=lines.next()?.strip_prefix("sdl_version ")?.to_string();
+    let mut hashes=BTreeMap::new();
+    for line in lines {
+        let (hash,name)=line.split_once(' ')?;
+        hashes.insert(name.to_string(),hash.to_string());
+    }
+
+    Some(FilesManifest {sdl_version, hashes})
+}
+
+fn write_manifest(path: &Path, manifest: &FilesManifest)->SdlApkResult<()> {
+    let mut content=format!("sdl_version {}\n",manifest.sdl_version);
+    for (name,hash) in &manifest.hashes {
+        content.push_str(&format!("{} {}\n",hash,name));
+    }
+
+    write(path,content).map_err(io_error(format!("Unable to write {}",path.display())))
+}
+
+impl ProjectFiles {
+    pub fn insert(&mut self, name: String, file: ProjectFile) {
+        self.files.insert(name,file);
+    }
+
+    pub fn get(&self, name: &str)->Option<&ProjectFile> {
+        self.files.get(name)
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.files.remove(name);
+    }
+
+    // The paths of the files below a directory, relative to it.
+    pub fn dir_files(&self, dir: &str)->Vec<String> {
+        let prefix=format!("{}/",dir.trim_end_matches('/'));
+        self.files.keys()
+            .filter_map(|name|name.strip_prefix(&prefix))
+            .map(|name|name.to_string())
+            .collect()
+    }
+
+    // Leave out all files below a directory of the template.
+    pub fn remove_dir(&mut self, dir: &str) {
+        let prefix=format!("{}/",dir.trim_end_matches('/'));
+        self.files.retain(|name,_|!name.starts_with(&prefix));
+    }
+
+    // Change the content of a text file.
+    pub fn change<F>(&mut self, name: &str, f: F)->SdlApkResult<()>
+            where F: FnOnce(&str)->SdlApkResult<String> {
+        let file=self.files.get_mut(name)
+            .ok_or_else(||SdlApkError::Template(format!("{} not found",name)))?;
+        let content=std::str::from_utf8(&file.content)
+            .map_err(|_|SdlApkError::Template(format!("{} is not UTF-8",name)))?;
+        file.content=f(content)?.into_bytes();
+        Ok(())
+    }
+
+    // Write the files into the project dir. Files are only written if their
+    // content on disk differs or they are missing, so that gradle's up-to-date
+    // checks keep working, and files generated by an earlier build that are
+    // no longer part of the template are removed.
+    pub fn sync(&self, project_dir: &Path, sdl_version: &str)->SdlApkResult<()> {
+        let manifest_path=project_dir.join(MANIFEST_FILE);
+        let old=read_manifest(&manifest_path);
+        if let Some(old)=&old {
+            if old.sdl_version!=sdl_version {
+                println!("SDL changed from {} to {}, updating Android project.",old.sdl_version,sdl_version);
+            }
+        }
+
+        let mut manifest=FilesManifest {
+            sdl_version: sdl_version.to_string(),
+            hashes: BTreeMap::new()
+        };
+
+        for (name,file) in &self.files {
+            let path=project_dir.join(name);
+            let file_hash=hash(&file.content);
+            let unchanged=read(&path).map(|c|c==file.content).unwrap_or(false);
+
+            if !unchanged {
+                if let Some(parent)=path.parent() {
+                    create_dir_all(parent).map_err(io_error(format!("Unable to create {}",parent.display())))?;
+                }
+                write(&path,&file.content).map_err(io_error(format!("Unable to write {}",path.display())))?;
+            }
+
+            let metadata=path.metadata().map_err(io_error(format!("Unable to read {}",path.display())))?;
+            if is_executable(&metadata)!=file.executable {
+                set_executable(&path,file.executable)
+                    .map_err(io_error(format!("Unable to set permissions of {}",path.display())))?;
+            }
+
+            manifest.hashes.insert(name.clone(),file_hash);
+        }
+
+        // Remove orphans, and the directories they leave empty.
+        if let Some(old)=&old {
+            for name in old.hashes.keys() {
+                if self.files.contains_key(name) {
+                    continue;
+                }
+
+                let path=project_dir.join(name);
+                if path.is_file() {
+                    remove_file(&path).map_err(io_error(format!("Unable to remove {}",path.display())))?;
+                }
+
+                let mut dir=path.parent();
+                while let Some(d)=dir {
+                    if d==project_dir || remove_dir(d).is_err() {
+                        break;
+                    }
+                    dir=d.parent();
+                }
+            }
+        }
+
+        write_manifest(&manifest_path,&manifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::time::SystemTime;
+
+    fn project_dir(name: &str)->PathBuf {
+        let dir=std::env::temp_dir().join(format!("sdl-apk-{}-{}",name,std::process::id()));
+        let _=std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn files(entries: &[(&str,&str,bool)])->ProjectFiles {
+        let mut files=ProjectFiles::default();
+        for (name,content,executable) in entries {
+            files.insert(name.to_string(),ProjectFile {
+                content: content.as_bytes().to_vec(),
+                executable: *executable
+            });
+        }
+        files
+    }
+
+    fn modified(path: &Path)->SystemTime {
+        path.metadata().unwrap().modified().unwrap()
+    }
+
+    #[test]
+    fn sync_restores_edited_files() {
+        let dir=project_dir("sync-edited");
+        let files=files(&[("app/build.gradle","gradle",false),("gradlew","#!/bin/sh",true)]);
+        files.sync(&dir,"2.26.0").unwrap();
+
+        // Edited on disk, but the recorded hash still matches the template.
+        write(dir.join("app/build.gradle"),"edited").unwrap();
+        let gradlew_time=modified(&dir.join("gradlew"));
+        files.sync(&dir,"2.26.0").unwrap();
+
+        assert_eq!(read_to_string(dir.join("app/build.gradle")).unwrap(),"gradle");
+        assert_eq!(modified(&dir.join("gradlew")),gradlew_time);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sync_updates_executable_bit() {
+        let dir=project_dir("sync-executable");
+        let is_exec=|name: &str|is_executable(&dir.join(name).metadata().unwrap());
+
+        files(&[("gradlew","#!/bin/sh",false)]).sync(&dir,"2.26.0").unwrap();
+        assert!(!is_exec("gradlew"));
+
+        files(&[("gradlew","#!/bin/sh",true)]).sync(&dir,"2.26.0").unwrap();
+        assert!(is_exec("gradlew"));
+
+        files(&[("gradlew","#!/bin/sh",false)]).sync(&dir,"2.26.0").unwrap();
+        assert!(!is_exec("gradlew"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
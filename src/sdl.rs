@@ -0,0 +1,402 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::fs::{copy, create_dir_all, read_to_string, write};
+use std::time::UNIX_EPOCH;
+use semver::Version;
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+use crate::util::*;
+use crate::error::*;
+use crate::android_project::*;
+use crate::workspace::*;
+use crate::build_bin_as_lib::*;
+use crate::BuildProfile;
+
+fn get_define(header: &str, name: &str)->Option<u64> {
+    header.lines().find_map(|line|{
+        let mut parts=line.split_whitespace();
+        if parts.next()!=Some("#define") || parts.next()!=Some(name) {
+            return None;
+        }
+
+        parts.next().and_then(|v|v.parse().ok())
+    })
+}
+
+// The version of an SDL source tree, from include/SDL_version.h for SDL2
+// or include/SDL3/SDL_version.h for SDL3.
+pub fn get_sdl_version(sdl_dir: &Path)->SdlApkResult<Version> {
+    let (header_path,patch_define)=if sdl_dir.join("include/SDL3/SDL_version.h").is_file() {
+        (sdl_dir.join("include/SDL3/SDL_version.h"),"SDL_MICRO_VERSION")
+    } else {
+        (sdl_dir.join("include/SDL_version.h"),"SDL_PATCHLEVEL")
+    };
+
+    let header=read_to_string(&header_path)
+        .map_err(io_error(format!("Unable to read {}",header_path.display())))?;
+
+    match (
+        get_define(&header,"SDL_MAJOR_VERSION"),
+        get_define(&header,"SDL_MINOR_VERSION"),
+        get_define(&header,patch_define)
+    ) {
+        (Some(major),Some(minor),Some(patch))=>Ok(Version::new(major,minor,patch)),
+        _=>Err(SdlApkError::Io(
+            format!("Unable to find SDL version in {}",header_path.display()),
+            std::io::Error::from(std::io::ErrorKind::InvalidData)
+        ))
+    }
+}
+
+// The SDL version a sys crate is made for. sdl2-sys bundles the SDL
+// sources, sdl3-sys has the version in its build metadata, e.g.
+// 0.1.0+SDL3-3.1.6. Older sdl2-sys releases have neither.
+fn get_sys_crate_sdl_version(sys_version: &Version, sys_dir: &Path)->SdlApkResult<Option<Version>> {
+    let bundled=sys_dir.join("SDL");
+    if bundled.join("include/SDL_version.h").is_file()
+            || bundled.join("include/SDL3/SDL_version.h").is_file() {
+        return get_sdl_version(&bundled).map(Some);
+    }
+
+    Ok(sys_version.build.as_str()
+        .split_once('-')
+        .and_then(|(_,v)|Version::parse(v).ok()))
+}
+
+// Compare the SDL sources with the SDL version that sdl2-sys or sdl3-sys
+// was made for. A different minor version gives a warning, a different
+// major version is an error unless allowed.
+pub fn check_sdl_version(package: &ResolvedPackage, sdl: &SdlSources, allow_mismatch: bool)->SdlApkResult<()> {
+    let (sys_name,sys_version,sys_dir)=match find_dependency(package,&["sdl2-sys","sdl3-sys"])? {
+        Some(dep)=>dep,
+        None=>return Ok(())
+    };
+
+    let expected=match get_sys_crate_sdl_version(&sys_version,&sys_dir)? {
+        Some(v)=>v,
+        None=>return Ok(())
+    };
+
+    let message=format!(
+        "SDL in {} is version {}, but {} {} is made for SDL {}",
+        sdl.dir.display(),sdl.version,sys_name,sys_version,expected
+    );
+
+    if sdl.version.major!=expected.major && !allow_mismatch {
+        return Err(SdlApkError::SdlVersionMismatch(format!(
+            "{}. Use --allow-sdl-mismatch to build anyway",message
+        )));
+    }
+
+    if sdl.version.major!=expected.major || sdl.version.minor!=expected.minor {
+        eprintln!("Warning: {}.",message);
+    }
+
+    Ok(())
+}
+
+// A hash of the state of a source tree built with ndk-build. File sizes
+// and modification times are used rather than contents, to keep it fast.
+// Build outputs and version control files are left out.
+fn get_source_tree_hash(source_dir: &Path)->SdlApkResult<String> {
+    let mut hasher=Sha256::new();
+    let walker=WalkDir::new(source_dir).sort_by_file_name().into_iter()
+        .filter_entry(|e|!matches!(e.file_name().to_str(),Some(".git"|"libs"|"obj")));
+
+    for entry in walker {
+        let entry=entry.map_err(walkdir_error(format!("Unable to read {}",source_dir.display())))?;
+
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let metadata=entry.metadata()
+            .map_err(walkdir_error(format!("Unable to read {}",entry.path().display())))?;
+        let modified=metadata.modified().ok()
+            .and_then(|m|m.duration_since(UNIX_EPOCH).ok())
+            .map(|d|d.as_nanos())
+            .unwrap_or(0);
+
+        hasher.update(format!("{} {} {}\n",entry.path().display(),metadata.len(),modified));
+    }
+
+    Ok(format!("{:x}",hasher.finalize()))
+}
+
+// A satellite library from sdl_libraries in [package.metadata.android],
+// e.g. SDL2_image.
+#[derive(Clone,Debug)]
+pub struct SdlLibrary {
+    pub name: String,
+    pub source_dir: PathBuf
+}
+
+impl SdlLibrary {
+    pub fn file_name(&self)->String {
+        format!("lib{}.so",self.name)
+    }
+}
+
+// The SDL source tree from $SDL and the satellite libraries to build with
+// it. With static_link, SDL is built and linked by the sys crate instead,
+// and the sources only provide the Android project and Java classes.
+#[derive(Clone,Debug)]
+pub struct SdlSources {
+    pub dir: PathBuf,
+    pub version: Version,
+    pub libraries: Vec<SdlLibrary>,
+    pub static_link: bool
+}
+
+impl SdlSources {
+    // The name of the SDL library and module, SDL2 or SDL3.
+    pub fn name(&self)->String {
+        format!("SDL{}",self.version.major)
+    }
+
+    pub fn file_name(&self)->String {
+        format!("lib{}.so",self.name())
+    }
+}
+
+// The SDL sources bundled with sdl2-sys, or with sdl3-src for sdl3-sys.
+fn get_vendored_sdl_dir(package: &ResolvedPackage)->SdlApkResult<PathBuf> {
+    let (name,_,dir)=find_dependency(package,&["sdl2-sys","sdl3-src"])?
+        .ok_or_else(||SdlApkError::BadMetadata(
+            "sdl_static is set, but SDL is not set and there is no sdl2-sys or sdl3-src dependency".to_string()
+        ))?;
+
+    let sdl_dir=dir.join("SDL");
+    if !sdl_dir.join("android-project").is_dir() {
+        return Err(SdlApkError::BadMetadata(format!(
+            "sdl_static is set, but SDL is not set and {} has no SDL sources in {}",
+            name,sdl_dir.display()
+        )));
+    }
+
+    Ok(sdl_dir)
+}
+
+// Find the SDL sources. The major version is detected from the sources,
+// and must match sdl_version in [package.metadata.android] if it is set.
+// Satellite library sources are found using the SDL_IMAGE, SDL_TTF,
+// SDL_MIXER and SDL_NET environment variables. With sdl_static, $SDL is
+// optional and defaults to the SDL sources vendored by the sys crate.
+pub fn get_sdl_sources(package: &ResolvedPackage)->SdlApkResult<SdlSources> {
+    let manifest_path=&package.manifest_path;
+    let static_link=get_toml_bool(manifest_path,vec!["package","metadata","android","sdl_static"])?
+        .unwrap_or(false);
+
+    let dir=match get_env_var("SDL") {
+        Ok(dir)=>PathBuf::from(dir),
+        Err(_) if static_link=>get_vendored_sdl_dir(package)?,
+        Err(e)=>return Err(e)
+    };
+    let version=get_sdl_version(&dir)?;
+
+    if !matches!(version.major,2|3) {
+        return Err(SdlApkError::SdlVersionMismatch(format!(
+            "SDL in {} is version {}, only SDL2 and SDL3 are supported",
+            dir.display(),version
+        )));
+    }
+
+    if let Some(major)=get_toml_integer(manifest_path,vec!["package","metadata","android","sdl_version"])? {
+        if major!=version.major as i64 {
+            return Err(SdlApkError::SdlVersionMismatch(format!(
+                "sdl_version is {}, but SDL in {} is version {}",
+                major,dir.display(),version
+            )));
+        }
+    }
+
+    let names=get_toml_string_array(manifest_path,vec!["package","metadata","android","sdl_libraries"])?
+        .unwrap_or_default();
+
+    if static_link && !names.is_empty() {
+        return Err(SdlApkError::BadMetadata("sdl_libraries can't be used with sdl_static".to_string()));
+    }
+
+    let libraries=names.iter().map(|name|{
+        let env_var=match name.as_str() {
+            "image"=>"SDL_IMAGE",
+            "ttf"=>"SDL_TTF",
+            "mixer"=>"SDL_MIXER",
+            "net"=>"SDL_NET",
+            _=>return Err(SdlApkError::BadMetadata(format!(
+                "Unknown SDL library {}, supported are image, ttf, mixer and net",name
+            )))
+        };
+
+        Ok(SdlLibrary {
+            name: format!("SDL{}_{}",version.major,name),
+            source_dir: PathBuf::from(get_env_var(env_var)?)
+        })
+    }).collect::<SdlApkResult<Vec<SdlLibrary>>>()?;
+
+    Ok(SdlSources {dir, version, libraries, static_link})
+}
+
+// The NDK revision from its source.properties.
+fn get_ndk_version(ndk_dir: &Path)->SdlApkResult<String> {
+    let properties_path=ndk_dir.join("source.properties");
+    let properties=read_to_string(&properties_path)
+        .map_err(io_error(format!("Unable to read {}",properties_path.display())))?;
+
+    Ok(properties.lines()
+        .filter_map(|line|line.split_once('='))
+        .find(|(key,_)|key.trim()=="Pkg.Revision")
+        .map(|(_,value)|value.trim().to_string())
+        .unwrap_or(properties))
+}
+
+pub fn get_sdl_cache_dir(target_dir: &Path, profile: BuildProfile, abi: &str)->PathBuf {
+    target_dir.join("sdl-build").join(profile.to_string()).join(abi)
+}
+
+// Build libSDL2.so or libSDL3.so and the satellite libraries with
+// ndk-build, and copy them to where the Rust linker finds them. The
+// output is cached per ABI under the target dir, and ndk-build only runs
+// if the sources, NDK, ABI, API level or profile changed.
+pub fn build_sdl_for_android(
+        targets: &[String],
+        sdl: &SdlSources,
+        profile: BuildProfile,
+        profile_dir: &str,
+        sdk_versions: &SdkVersions,
+        target_dir: &Path,
+        rebuild: bool
+    )->SdlApkResult<()> {
+    let sdl_dir=&sdl.dir;
+    let libraries=&sdl.libraries;
+    let ndk_dir=PathBuf::from(get_env_var("ANDROID_NDK_HOME")?);
+    let ndk_version=get_ndk_version(&ndk_dir)?;
+
+    let mut file_names=vec![sdl.file_name()];
+    let mut sources=format!("{} {}\n",sdl.name(),get_source_tree_hash(sdl_dir)?);
+    for library in libraries {
+        file_names.push(library.file_name());
+        sources.push_str(&format!("{} {}\n",library.name,get_source_tree_hash(&library.source_dir)?));
+    }
+
+    let mut stale=vec![];
+    for rust_name in targets {
+        let abi=get_target_android_name(rust_name)?;
+        let cache_dir=get_sdl_cache_dir(target_dir,profile,abi);
+        let key=format!(
+            "{}ndk {}\nabi {}\napi {}\nprofile {}\n",
+            sources,ndk_version,abi,sdk_versions.min,profile
+        );
+
+        let cached=read_to_string(cache_dir.join("build-key")).ok()==Some(key.clone())
+            && file_names.iter().all(|f|cache_dir.join(f).is_file());
+        if rebuild || !cached {
+            stale.push((abi,cache_dir,key));
+        }
+    }
+
+    if !stale.is_empty() {
+        // With satellite libraries, build them together with SDL, so that
+        // their dependency on the SDL module can be resolved.
+        let build_script=if libraries.is_empty() {
+            PathBuf::from("./Android.mk")
+        } else {
+            let build_script=target_dir.join("sdl-build/Android.mk");
+            let mut content=format!("include {}\n",sdl_dir.join("Android.mk").display());
+            for library in libraries {
+                content.push_str(&format!("include {}\n",library.source_dir.join("Android.mk").display()));
+            }
+
+            create_dir_all(target_dir.join("sdl-build")).map_err(io_error("Unable to create SDL cache dir"))?;
+            write(&build_script,content).map_err(io_error("Unable to write SDL build script"))?;
+            build_script
+        };
+
+        let abis=stale.iter().map(|(abi,_,_)|*abi).collect::<Vec<&str>>().join(" ");
+        let jobs=std::thread::available_parallelism().map(|n|n.get()).unwrap_or(1);
+        run_command(Command::new(ndk_dir.join("ndk-build"))
+            .args([
+                "NDK_PROJECT_PATH=.",
+                &format!("APP_BUILD_SCRIPT={}",build_script.display()),
+                &format!("APP_PLATFORM=android-{}",sdk_versions.min),
+                &format!("APP_ABI={}",abis),
+                &format!("APP_OPTIM={}",profile),
+                &format!("-j{}",jobs)
+            ])
+            .current_dir(sdl_dir)
+        )?;
+
+        for (abi,cache_dir,key) in stale {
+            create_dir_all(&cache_dir).map_err(io_error("Unable to create SDL cache dir"))?;
+            for file_name in &file_names {
+                let lib_path=sdl_dir.join("libs").join(abi).join(file_name);
+                if !lib_path.is_file() {
+                    return Err(SdlApkError::Io(
+                        format!("ndk-build did not build {} for {}, {} is missing",file_name,abi,lib_path.display()),
+                        std::io::Error::from(std::io::ErrorKind::NotFound)
+                    ));
+                }
+
+                copy(&lib_path,cache_dir.join(file_name))
+                    .map_err(io_error(format!("Unable to copy {}",lib_path.display())))?;
+            }
+
+            write(cache_dir.join("build-key"),key).map_err(io_error("Unable to write SDL cache key"))?;
+        }
+    }
+
+    for rust_name in targets {
+        let abi=get_target_android_name(rust_name)?;
+        let rust_dir=target_dir
+            .join(rust_name)
+            .join(profile_dir)
+            .join("deps");
+
+        create_dir_all(&rust_dir).map_err(io_error("Unable to create target dir"))?;
+        for file_name in &file_names {
+            copy(
+                get_sdl_cache_dir(target_dir,profile,abi).join(file_name),
+                rust_dir.join(file_name)
+            ).map_err(io_error("Unable to copy SDL dependencies"))?;
+        }
+    }
+
+    Ok(())
+}
+
+// The env vars for build scripts when SDL is built by the sys crate, so
+// that its cmake build and any cc builds use the NDK. Each target gets a
+// toolchain file that sets the ABI and API level and includes the NDK's
+// android.toolchain.cmake. It has the same name, so that the cmake crate
+// leaves the compiler flags to it.
+pub fn get_static_build_env(
+        targets: &[String],
+        api_level: i64,
+        target_dir: &Path
+    )->SdlApkResult<Vec<(String,String)>> {
+    let ndk_dir=PathBuf::from(get_env_var("ANDROID_NDK_HOME")?);
+    let bin_dir=ndk_dir.join("toolchains/llvm/prebuilt/linux-x86_64/bin");
+
+    let mut env=vec![];
+    for rust_name in targets {
+        let abi=get_target_android_name(rust_name)?;
+        let toolchain_dir=target_dir.join("sdl-static").join(abi);
+        let toolchain_file=toolchain_dir.join("android.toolchain.cmake");
+        let content=format!(
+            "set(ANDROID_ABI {})\nset(ANDROID_PLATFORM android-{})\ninclude({})\n",
+            abi,api_level,ndk_dir.join("build/cmake/android.toolchain.cmake").display()
+        );
+
+        create_dir_all(&toolchain_dir).map_err(io_error("Unable to create toolchain dir"))?;
+        write(&toolchain_file,content).map_err(io_error("Unable to write cmake toolchain file"))?;
+
+        let clang=ndk_dir.join(get_target_linker(rust_name,api_level)?);
+        let target=rust_name.replace('-',"_");
+        env.push((format!("CMAKE_TOOLCHAIN_FILE_{}",target),path_to_string(&toolchain_file)?));
+        env.push((format!("CC_{}",target),path_to_string(&clang)?));
+        env.push((format!("CXX_{}",target),format!("{}++",path_to_string(&clang)?)));
+        env.push((format!("AR_{}",target),path_to_string(&bin_dir.join("llvm-ar"))?));
+    }
+
+    Ok(env)
+}
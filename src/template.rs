@@ -0,0 +1,184 @@
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use std::fs::read;
+use walkdir::WalkDir;
+use crate::util::*;
+use crate::error::*;
+use crate::project_files::*;
+
+pub type TemplateVars=BTreeMap<String,String>;
+
+// The template_dir key in [package.metadata.android], relative to the
+// package manifest.
+pub fn get_template_dir(manifest_path: &Path)->SdlApkResult<Option<PathBuf>> {
+    let manifest_dir=manifest_path.parent().unwrap_or(Path::new("."));
+    let dir=get_toml_string(manifest_path,vec!["package","metadata","android","template_dir"])?
+        .map(|d|manifest_dir.join(d));
+
+    if let Some(dir)=&dir {
+        if !dir.is_dir() {
+            return Err(SdlApkError::BadMetadata(format!("Template dir not found: {}",dir.display())));
+        }
+    }
+
+    Ok(dir)
+}
+
+// Replace {{name}} with the value of the variable. Unknown variables are
+// an error, so that typos don't end up in the project. Only an identifier
+// between the braces is a variable, any other {{ is copied as is, so that
+// e.g. Kotlin or Groovy code in a template doesn't need escaping.
+pub fn render_template(content: &str, vars: &TemplateVars)->Result<String,String> {
+    let mut out=String::new();
+    let mut rest=content;
+    while let Some(start)=rest.find("{{") {
+        out.push_str(&rest[..start]);
+        rest=&rest[start..];
+        let name=match parse_variable(rest) {
+            Some((name,len))=>{
+                rest=&rest[len..];
+                name
+            },
+            None=>{
+                out.push('{');
+                rest=&rest[1..];
+                continue;
+            }
+        };
+
+        let value=vars.get(name).ok_or_else(||format!(
+            "Unknown variable {{{{{}}}}}, available are: {}",
+            name,
+            vars.keys().cloned().collect::<Vec<String>>().join(", ")
+        ))?;
+        out.push_str(value);
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+// Parse "{{ name }}" at the start of s, returning the name and the length
+// of the whole variable.
+fn parse_variable(s: &str)->Option<(&str,usize)> {
+    let inner=s.strip_prefix("{{")?;
+    let end=inner.find("}}")?;
+    let name=inner[..end].trim();
+    let mut chars=name.chars();
+    let valid=chars.next().is_some_and(|c|c.is_ascii_alphabetic() || c=='_')
+        && chars.all(|c|c.is_ascii_alphanumeric() || c=='_');
+
+    if !valid {
+        return None;
+    }
+
+    Some((name,end+4))
+}
+
+// The files the Android project is generated from: SDL's android-project
+// with an optional user template dir on top.
+pub struct ProjectTemplate {
+    pub sdl_dir: PathBuf,
+    pub overlay_dir: Option<PathBuf>,
+    pub vars: TemplateVars
+}
+
+impl ProjectTemplate {
+    fn render(&self, path: &Path, content: &str)->SdlApkResult<String> {
+        render_template(content,&self.vars)
+            .map_err(|e|SdlApkError::Template(format!("{}: {}",path.display(),e)))
+    }
+
+    // Collect the files of the template. Files in the overlay dir take
+    // precedence over the SDL ones, and are rendered if they are text.
+    pub fn collect(&self)->SdlApkResult<ProjectFiles> {
+        let mut files=ProjectFiles::default();
+        self.collect_dir(&self.sdl_dir,false,&mut files)?;
+        if let Some(overlay_dir)=&self.overlay_dir {
+            self.collect_dir(overlay_dir,true,&mut files)?;
+        }
+
+        Ok(files)
+    }
+
+    fn collect_dir(&self, dir: &Path, render: bool, files: &mut ProjectFiles)->SdlApkResult<()> {
+        for entry in WalkDir::new(dir).follow_links(render) {
+            let entry=entry.map_err(walkdir_error(format!("Unable to read {}",dir.display())))?;
+
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let rel=entry.path().strip_prefix(dir).unwrap_or(entry.path());
+            let name=path_to_name(rel);
+
+            let content=read(entry.path()).map_err(io_error(format!("Unable to read {}",entry.path().display())))?;
+            let content=match String::from_utf8(content) {
+                Ok(text) if render=>self.render(entry.path(),&text)?.into_bytes(),
+                Ok(text)=>text.into_bytes(),
+                Err(e)=>e.into_bytes()
+            };
+
+            let executable=entry.metadata()
+                .map(|m|is_executable(&m))
+                .unwrap_or(false);
+
+            files.insert(name,ProjectFile {content, executable});
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars()->TemplateVars {
+        let mut vars=TemplateVars::new();
+        vars.insert("app_id".to_string(),"com.example.game".to_string());
+        vars.insert("title".to_string(),"Game".to_string());
+        vars
+    }
+
+    #[test]
+    fn variables_are_replaced() {
+        assert_eq!(
+            render_template("id={{app_id}} title={{ title }}{{title}}",&vars()).unwrap(),
+            "id=com.example.game title=GameGame"
+        );
+        assert_eq!(render_template("no variables } {",&vars()).unwrap(),"no variables } {");
+        assert_eq!(render_template("",&vars()).unwrap(),"");
+    }
+
+    #[test]
+    fn values_are_not_rendered_again() {
+        let mut vars=vars();
+        vars.insert("title".to_string(),"{{app_id}}".to_string());
+        assert_eq!(render_template("{{title}}",&vars).unwrap(),"{{app_id}}");
+    }
+
+    #[test]
+    fn unknown_variable() {
+        let e=render_template("{{ app_name }}",&vars()).unwrap_err();
+        assert_eq!(e,"Unknown variable {{app_name}}, available are: app_id, title");
+    }
+
+    #[test]
+    fn non_variables_are_kept() {
+        for text in [
+            "a {{title",
+            "{{}}",
+            "{{ 1st }}",
+            "{{ a.b }}",
+            "val m = mapOf{{ \"a\" to 1 }}",
+            "{{{{",
+        ] {
+            assert_eq!(render_template(text,&vars()).unwrap(),text);
+        }
+
+        assert_eq!(render_template("{{title}} {{",&vars()).unwrap(),"Game {{");
+        assert_eq!(render_template("{{{title}}}",&vars()).unwrap(),"{Game}");
+        assert_eq!(render_template("{{ x {{title}}",&vars()).unwrap(),"{{ x Game");
+    }
+}
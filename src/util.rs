@@ -1,29 +1,68 @@
 use std::env;
 use std::fs::read_to_string;
+use std::io::ErrorKind;
+use std::process::{Command, Output};
 use toml::value::Value;
 use toml::Table;
 use std::path::Path;
+use crate::error::*;
 
-pub fn get_env_var(key: &str) -> String {
-    for (k, v) in env::vars() {
-        if k == key {
-            return v;
-        }
+pub fn get_env_var(key: &str) -> SdlApkResult<String> {
+    env::var(key).map_err(|_|SdlApkError::MissingEnvVar(key.to_string()))
+}
+
+fn command_line(cmd: &Command) -> String {
+    let mut parts=vec![cmd.get_program().to_string_lossy().to_string()];
+    parts.extend(cmd.get_args().map(|a|a.to_string_lossy().to_string()));
+    parts.join(" ")
+}
+
+fn spawn_error(cmd: &Command) -> impl FnOnce(std::io::Error) -> SdlApkError + '_ {
+    move |e| match e.kind() {
+        ErrorKind::NotFound => SdlApkError::ToolNotFound(cmd.get_program().to_string_lossy().to_string()),
+        _ => SdlApkError::Io(format!("Unable to run {}",command_line(cmd)),e),
+    }
+}
+
+// Run a command with inherited stdio, failing unless it exits successfully.
+pub fn run_command(cmd: &mut Command) -> SdlApkResult<()> {
+    let status=cmd.status().map_err(spawn_error(cmd))?;
+    if !status.success() {
+        return Err(SdlApkError::ToolFailed {
+            command: command_line(cmd),
+            status
+        });
     }
 
-    panic!("Need env var: {}", key);
+    Ok(())
 }
 
-fn get_toml_string_rec(table: &Table, mut path: Vec<&str>) -> Option<String> {
-    if path.len() == 1 {
-        if !table.contains_key(path[0]) {
-            return None;
-        }
+// Run a command and capture its output, failing unless it exits successfully.
+pub fn run_command_output(cmd: &mut Command) -> SdlApkResult<Output> {
+    let output=cmd.output().map_err(spawn_error(cmd))?;
+    if !output.status.success() {
+        return Err(SdlApkError::ToolFailed {
+            command: command_line(cmd),
+            status: output.status
+        });
+    }
+
+    Ok(output)
+}
 
-        return match table[path[0]].clone() {
-            Value::String(s) => Some(s),
-            _ => None,
-        };
+pub fn path_to_string(path: &Path) -> SdlApkResult<String> {
+    match path.to_str() {
+        Some(s) => Ok(s.to_string()),
+        None => Err(SdlApkError::Io(
+            format!("Path is not valid UTF-8: {}",path.display()),
+            std::io::Error::from(ErrorKind::InvalidData)
+        )),
+    }
+}
+
+fn get_toml_value_rec(table: &Table, mut path: Vec<&str>) -> Option<Value> {
+    if path.len() == 1 {
+        return table.get(path[0]).cloned();
     }
 
     let id = path.remove(0);
@@ -32,20 +71,61 @@ fn get_toml_string_rec(table: &Table, mut path: Vec<&str>) -> Option<String> {
 	}
 
     match table[id].clone() {
-        Value::Table(t) => get_toml_string_rec(&t, path),
+        Value::Table(t) => get_toml_value_rec(&t, path),
         _ => None,
     }
 }
 
-pub fn get_toml_string(file_name: &Path, path: Vec<&str>) -> Option<String> {
-    let config = {
-        let f = read_to_string(file_name);
-        if let Ok(f) = f {
-            f.parse::<Table>().unwrap()
-        } else {
-            panic!("Unable to read {}",file_name.display());
-        }
-    };
+pub fn get_toml_value(file_name: &Path, path: Vec<&str>) -> SdlApkResult<Option<Value>> {
+    let config = read_to_string(file_name)
+        .map_err(io_error(format!("Unable to read {}",file_name.display())))?
+        .parse::<Table>()
+        .map_err(|e|SdlApkError::BadMetadata(format!("{}: {}",file_name.display(),e)))?;
 
-    get_toml_string_rec(&config, path)
+    Ok(get_toml_value_rec(&config, path))
+}
+
+fn type_error(file_name: &Path, path: &[&str], expected: &str) -> SdlApkError {
+    SdlApkError::BadMetadata(format!("{}: {} should be {}",file_name.display(),path.join("."),expected))
+}
+
+pub fn get_toml_string(file_name: &Path, path: Vec<&str>) -> SdlApkResult<Option<String>> {
+    match get_toml_value(file_name, path.clone())? {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(_) => Err(type_error(file_name,&path,"a string")),
+    }
+}
+
+pub fn get_toml_integer(file_name: &Path, path: Vec<&str>) -> SdlApkResult<Option<i64>> {
+    match get_toml_value(file_name, path.clone())? {
+        None => Ok(None),
+        Some(Value::Integer(i)) => Ok(Some(i)),
+        Some(_) => Err(type_error(file_name,&path,"an integer")),
+    }
+}
+
+pub fn get_toml_bool(file_name: &Path, path: Vec<&str>) -> SdlApkResult<Option<bool>> {
+    match get_toml_value(file_name, path.clone())? {
+        None => Ok(None),
+        Some(Value::Boolean(b)) => Ok(Some(b)),
+        Some(_) => Err(type_error(file_name,&path,"a boolean")),
+    }
+}
+
+pub fn get_toml_string_array(file_name: &Path, path: Vec<&str>) -> SdlApkResult<Option<Vec<String>>> {
+    match get_toml_value(file_name, path.clone())? {
+        None => Ok(None),
+        Some(Value::Array(a)) => {
+            let mut res=vec![];
+            for v in a {
+                match v {
+                    Value::String(s) => res.push(s),
+                    _ => return Err(type_error(file_name,&path,"an array of strings")),
+                }
+            }
+            Ok(Some(res))
+        },
+        Some(_) => Err(type_error(file_name,&path,"an array of strings")),
+    }
 }
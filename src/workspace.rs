@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+use std::collections::{HashSet, VecDeque};
+use cargo::core::Workspace;
+use cargo::util::Config as CargoConfig;
+use crate::error::*;
+
+// Create a cargo config. The target dir, if not given, comes from
+// CARGO_TARGET_DIR or the cargo config files, as with cargo itself. The
+// env vars are added to the [env] section, for build scripts.
+pub fn create_cargo_config(target_dir: Option<&Path>, env: &[(String,String)])->SdlApkResult<CargoConfig> {
+    let cli_config:Vec<String>=env.iter()
+        .map(|(k,v)|format!("env.{}={}",k,toml::Value::String(v.clone())))
+        .collect();
+
+    let mut cargo_config = CargoConfig::default().map_err(SdlApkError::Workspace)?;
+    cargo_config.configure(
+    	0, // verbose
+    	false, // quiet
+    	None, // color
+    	cargo_config.frozen(), // frozen
+    	cargo_config.locked(), // locked
+    	cargo_config.offline(), // offline
+    	&target_dir.map(PathBuf::from), // target dir
+    	&[], // unstable flags
+    	&cli_config // cli config
+    ).map_err(SdlApkError::Workspace)?;
+
+    Ok(cargo_config)
+}
+
+// The workspace member being packaged, and where its build outputs go.
+#[derive(Clone,Debug)]
+pub struct ResolvedPackage {
+    pub name: String,
+    pub manifest_path: PathBuf,
+    pub target_dir: PathBuf,
+    pub bins: Vec<String>,
+    pub version: semver::Version
+}
+
+impl ResolvedPackage {
+    pub fn android_project_dir(&self)->PathBuf {
+        self.target_dir.join("android-project")
+    }
+}
+
+// Find the package to build. If no package name is given, the package at
+// the manifest path is used. The resolved target dir is used for every
+// file the build reads or writes, and is relative to the current dir if
+// given.
+pub fn resolve_package(
+        manifest_path: &Path,
+        package: Option<&str>,
+        target_dir: Option<&Path>
+    )->SdlApkResult<ResolvedPackage> {
+    // Cargo keeps a relative target dir as is, but ndk-build and build
+    // scripts run in other directories.
+    let target_dir=match target_dir {
+        Some(dir)=>Some(std::env::current_dir()
+            .map_err(io_error("Unable to get current dir"))?
+            .join(dir)),
+        None=>None
+    };
+
+    let cargo_config=create_cargo_config(target_dir.as_deref(),&[])?;
+    let workspace=Workspace::new(manifest_path, &cargo_config).map_err(SdlApkError::Workspace)?;
+
+    let pkg=match package {
+        Some(name)=>workspace.members()
+            .find(|p|p.name().as_str()==name)
+            .ok_or_else(||SdlApkError::Usage(format!("Package not found in workspace: {}",name)))?,
+        None=>workspace.current_opt()
+            .ok_or_else(||SdlApkError::Usage(format!(
+                "{} is a virtual manifest, use --package to select a workspace member",
+                manifest_path.display()
+            )))?
+    };
+
+    Ok(ResolvedPackage {
+        name: pkg.name().to_string(),
+        manifest_path: pkg.manifest_path().to_path_buf(),
+        target_dir: workspace.target_dir().into_path_unlocked(),
+        bins: pkg.targets().iter()
+            .filter(|t|t.is_bin())
+            .map(|t|t.name().to_string())
+            .collect(),
+        version: pkg.version().clone()
+    })
+}
+
+// Find the first package with one of the names in the resolved dependency
+// graph of the package, and the directory with its sources.
+pub fn find_dependency(
+        package: &ResolvedPackage,
+        names: &[&str]
+    )->SdlApkResult<Option<(String,semver::Version,PathBuf)>> {
+    let cargo_config=create_cargo_config(Some(&package.target_dir),&[])?;
+    let workspace=Workspace::new(&package.manifest_path, &cargo_config).map_err(SdlApkError::Workspace)?;
+    let (packages,resolve)=cargo::ops::resolve_ws(&workspace).map_err(SdlApkError::Workspace)?;
+
+    let root=workspace.members()
+        .find(|p|p.name().as_str()==package.name)
+        .ok_or_else(||SdlApkError::Usage(format!("Package not found in workspace: {}",package.name)))?
+        .package_id();
+
+    let mut seen=HashSet::new();
+    let mut queue=VecDeque::from([root]);
+    while let Some(id)=queue.pop_front() {
+        if !seen.insert(id) {
+            continue;
+        }
+
+        if names.contains(&id.name().as_str()) {
+            let pkg=packages.get_one(id).map_err(SdlApkError::Workspace)?;
+            return Ok(Some((id.name().to_string(),id.version().clone(),pkg.root().to_path_buf())));
+        }
+
+        queue.extend(resolve.deps(id).map(|(dep,_)|dep));
+    }
+
+    Ok(None)
+}
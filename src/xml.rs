@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+use xml::reader::{EventReader, ParserConfig, XmlEvent as ReaderEvent};
+use xml::writer::{EmitterConfig, XmlEvent as WriterEvent};
+use xml::name::OwnedName;
+use crate::error::*;
+
+// A minimal XML document model. Element and attribute names are kept
+// qualified, e.g. "android:name", so documents can be edited without
+// caring about namespace URIs. Comments are dropped.
+#[derive(Clone,Debug)]
+pub enum XmlNode {
+    Element(XmlElement),
+    Text(String)
+}
+
+#[derive(Clone,Debug)]
+pub struct XmlElement {
+    pub name: String,
+    pub namespaces: Vec<(String,String)>,
+    pub attributes: Vec<(String,String)>,
+    pub children: Vec<XmlNode>
+}
+
+fn qualified_name(name: &OwnedName)->String {
+    match &name.prefix {
+        Some(prefix)=>format!("{}:{}",prefix,name.local_name),
+        None=>name.local_name.clone()
+    }
+}
+
+impl XmlElement {
+    pub fn new(name: &str)->Self {
+        Self {
+            name: name.to_string(),
+            namespaces: vec![],
+            attributes: vec![],
+            children: vec![]
+        }
+    }
+
+    pub fn parse(content: &str)->SdlApkResult<XmlElement> {
+        let parser=EventReader::new_with_config(
+            content.as_bytes(),
+            ParserConfig::new().trim_whitespace(true)
+        );
+
+        let mut stack:Vec<XmlElement>=vec![];
+        let mut scopes:Vec<BTreeMap<String,String>>=vec![BTreeMap::new()];
+        for event in parser {
+            let event=event.map_err(|e|SdlApkError::Template(format!("Unable to parse XML: {}",e)))?;
+            match event {
+                ReaderEvent::StartElement{name,attributes,namespace}=>{
+                    // Only keep the namespace declarations made on this element.
+                    let parent_scope=scopes.last().cloned().unwrap_or_default();
+                    let mut element=XmlElement::new(&qualified_name(&name));
+                    for (prefix,uri) in &namespace {
+                        if matches!(prefix,"xml"|"xmlns") || uri.is_empty() || parent_scope.get(prefix)==Some(&uri.to_string()) {
+                            continue;
+                        }
+                        element.namespaces.push((prefix.to_string(),uri.to_string()));
+                    }
+                    for attr in attributes {
+                        element.attributes.push((qualified_name(&attr.name),attr.value));
+                    }
+                    scopes.push(namespace.0.clone());
+                    stack.push(element);
+                },
+                ReaderEvent::EndElement{..}=>{
+                    scopes.pop();
+                    let element=stack.pop()
+                        .ok_or_else(||SdlApkError::Template("Unbalanced XML".to_string()))?;
+                    match stack.last_mut() {
+                        Some(parent)=>parent.children.push(XmlNode::Element(element)),
+                        None=>return Ok(element)
+                    }
+                },
+                ReaderEvent::Characters(s) | ReaderEvent::CData(s)=>{
+                    if let Some(parent)=stack.last_mut() {
+                        parent.children.push(XmlNode::Text(s));
+                    }
+                },
+                _=>{}
+            }
+        }
+
+        Err(SdlApkError::Template("XML document has no root element".to_string()))
+    }
+
+    pub fn to_xml_string(&self)->SdlApkResult<String> {
+        let mut out:Vec<u8>=vec![];
+        let mut writer=EmitterConfig::new()
+            .perform_indent(true)
+            .indent_string("    ")
+            .create_writer(&mut out);
+
+        self.write(&mut writer)
+            .map_err(|e|SdlApkError::Template(format!("Unable to write XML: {}",e)))?;
+
+        out.push(b'\n');
+        String::from_utf8(out)
+            .map_err(|e|SdlApkError::Template(format!("Unable to write XML: {}",e)))
+    }
+
+    fn write<W: std::io::Write>(&self, writer: &mut xml::EventWriter<W>)->xml::writer::Result<()> {
+        let mut start=WriterEvent::start_element(&*self.name);
+        for (prefix,uri) in &self.namespaces {
+            start=start.ns(&**prefix,&**uri);
+        }
+        for (name,value) in &self.attributes {
+            start=start.attr(&**name,value);
+        }
+        writer.write(start)?;
+
+        for child in &self.children {
+            match child {
+                XmlNode::Element(e)=>e.write(writer)?,
+                XmlNode::Text(s)=>writer.write(WriterEvent::characters(s))?
+            }
+        }
+
+        writer.write(WriterEvent::end_element())
+    }
+
+    pub fn get_attribute(&self, name: &str)->Option<&str> {
+        self.attributes.iter().find(|(k,_)|k==name).map(|(_,v)|&**v)
+    }
+
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        match self.attributes.iter_mut().find(|(k,_)|k==name) {
+            Some(attr)=>attr.1=value.to_string(),
+            None=>self.attributes.push((name.to_string(),value.to_string()))
+        }
+    }
+
+    pub fn with_attribute(mut self, name: &str, value: &str)->Self {
+        self.set_attribute(name,value);
+        self
+    }
+
+    pub fn get_child_mut(&mut self, name: &str)->Option<&mut XmlElement> {
+        self.children.iter_mut().find_map(|c|match c {
+            XmlNode::Element(e) if e.name==name=>Some(e),
+            _=>None
+        })
+    }
+
+    // Replace all children with the given name. The new children go where
+    // the first old one was, or before the first child named "before", or
+    // last.
+    pub fn replace_children(&mut self, name: &str, children: Vec<XmlElement>, before: &str) {
+        let is_named=|c:&XmlNode,n:&str|matches!(c,XmlNode::Element(e) if e.name==n);
+        let index=self.children.iter().position(|c|is_named(c,name))
+            .or_else(||self.children.iter().position(|c|is_named(c,before)))
+            .unwrap_or(self.children.len());
+
+        self.children.retain(|c|!is_named(c,name));
+        for (i,child) in children.into_iter().enumerate() {
+            self.children.insert(index+i,XmlNode::Element(child));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str=include_str!("../testdata/sdl-2.26/AndroidManifest.xml");
+
+    fn element_names(element: &XmlElement)->Vec<String> {
+        element.children.iter().filter_map(|c|match c {
+            XmlNode::Element(e)=>Some(e.name.clone()),
+            _=>None
+        }).collect()
+    }
+
+    #[test]
+    fn parse_keeps_qualified_names() {
+        let root=XmlElement::parse(MANIFEST).unwrap();
+        assert_eq!(root.name,"manifest");
+        assert_eq!(root.namespaces,vec![(
+            "android".to_string(),
+            "http://schemas.android.com/apk/res/android".to_string()
+        )]);
+        assert_eq!(root.get_attribute("package"),Some("org.libsdl.app"));
+        assert_eq!(root.get_attribute("android:installLocation"),Some("auto"));
+        assert_eq!(element_names(&root),vec![
+            "uses-feature","uses-feature","uses-feature","uses-feature","uses-feature","uses-feature",
+            "uses-permission","application"
+        ]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let root=XmlElement::parse(MANIFEST).unwrap();
+        let xml=root.to_xml_string().unwrap();
+        assert!(!xml.contains("<!--"));
+        assert_eq!(xml.matches("xmlns:android=").count(),1);
+
+        let again=XmlElement::parse(&xml).unwrap();
+        assert_eq!(again.to_xml_string().unwrap(),xml);
+        assert_eq!(format!("{:?}",again),format!("{:?}",root));
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        let mut element=XmlElement::new("string").with_attribute("name","a&b");
+        element.children.push(XmlNode::Text("<Tom & Jerry>".to_string()));
+        let xml=element.to_xml_string().unwrap();
+        assert!(xml.contains("name=\"a&amp;b\""));
+        assert!(xml.contains("&lt;Tom &amp; Jerry&gt;"));
+
+        let parsed=XmlElement::parse(&xml).unwrap();
+        assert_eq!(parsed.get_attribute("name"),Some("a&b"));
+        assert!(matches!(&parsed.children[..],[XmlNode::Text(t)] if t=="<Tom & Jerry>"));
+    }
+
+    #[test]
+    fn replace_children_position() {
+        let mut root=XmlElement::parse(MANIFEST).unwrap();
+        root.replace_children("uses-permission",vec![
+            XmlElement::new("uses-permission").with_attribute("android:name","android.permission.INTERNET")
+        ],"application");
+        root.replace_children("queries",vec![XmlElement::new("queries")],"application");
+        root.replace_children("uses-feature",vec![],"application");
+        assert_eq!(element_names(&root),vec!["uses-permission","queries","application"]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(XmlElement::parse(""),Err(SdlApkError::Template(_))));
+        assert!(matches!(XmlElement::parse("<a><b></a>"),Err(SdlApkError::Template(_))));
+    }
+}